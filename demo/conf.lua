@@ -0,0 +1 @@
+return {width = 960, height = 540, title = "Pesto Demo", vsync = true, resizable = true}
@@ -19,8 +19,9 @@ print(pesto.json.encode(kitten2))
 local x = 0
 
 function pesto.update(dt)
-    pesto.graphics.circle(x, 350, 25)
     x = x + dt * 500
 
     if x > 600 then x = 400 end
 end
+
+function pesto.draw() pesto.graphics.circle(x, 350, 25) end
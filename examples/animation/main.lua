@@ -0,0 +1,32 @@
+-- A looping animation with one duration for every frame next to one that
+-- plays once with its own duration per frame, press space to replay it
+local sheet
+local looping
+local once
+
+function pesto.load()
+    sheet = pesto.graphics.newImage("sheet.png")
+
+    looping = pesto.graphics.newAnimation(sheet, 16, 16, 0.15)
+    once = pesto.graphics.newAnimation(sheet, 16, 16, { 0.1, 0.2, 0.4, 0.8 }, false)
+end
+
+function pesto.update(dt)
+    looping:update(dt)
+    once:update(dt)
+
+    if pesto.keyboard.isPressed("space") then
+        once:reset()
+    end
+end
+
+function pesto.draw()
+    looping:draw(400, 300, 0, 8)
+    once:draw(700, 300, 0, 8)
+
+    pesto.graphics.print("Frame " .. once:getFrame() .. " of " .. once:getFrameCount(), 700, 460)
+
+    if once:isFinished() then
+        pesto.graphics.print("Press space to replay", 700, 500)
+    end
+end
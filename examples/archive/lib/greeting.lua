@@ -0,0 +1 @@
+return {text = "Hello from inside the archive"}
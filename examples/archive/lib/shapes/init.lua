@@ -0,0 +1,7 @@
+local shapes = {}
+
+function shapes.square(x, y, size)
+    pesto.graphics.rectangle("fill", x, y, size, size)
+end
+
+return shapes
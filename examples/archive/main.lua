@@ -0,0 +1,15 @@
+-- Run with `pesto examples/archive.zip`, everything is loaded from the zip
+local greeting = require("lib.greeting")
+local shapes = require("lib.shapes")
+
+function pesto.update(dt)
+end
+
+function pesto.draw()
+    pesto.graphics.print(greeting.text, 10, 40)
+    shapes.square(600, 320, 80)
+
+    for i, item in ipairs(pesto.filesystem.getDirectoryItems("lib", true)) do
+        pesto.graphics.print(item, 10, 80 + i * 40)
+    end
+end
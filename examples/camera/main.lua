@@ -0,0 +1,37 @@
+-- Scroll with the arrow keys, zoom with z and x, click to drop markers in
+-- the world while the text stays fixed to the screen
+local camera = { x = 0, y = 0, zoom = 1 }
+local markers = {}
+
+function pesto.update(dt)
+    if pesto.keyboard.isDown("left") then camera.x = camera.x - 300 * dt end
+    if pesto.keyboard.isDown("right") then camera.x = camera.x + 300 * dt end
+    if pesto.keyboard.isDown("up") then camera.y = camera.y - 300 * dt end
+    if pesto.keyboard.isDown("down") then camera.y = camera.y + 300 * dt end
+    if pesto.keyboard.isDown("z") then camera.zoom = camera.zoom * (1 + dt) end
+    if pesto.keyboard.isDown("x") then camera.zoom = camera.zoom / (1 + dt) end
+
+    if pesto.mouse.isPressed("left") then
+        pesto.graphics.setCamera(camera.x, camera.y, camera.zoom)
+        local x, y = pesto.graphics.toWorld(pesto.mouse.getPosition())
+        pesto.graphics.resetCamera()
+
+        table.insert(markers, { x = x, y = y })
+    end
+end
+
+function pesto.draw()
+    pesto.graphics.setCamera(camera.x, camera.y, camera.zoom)
+
+    for i = -10, 10 do
+        pesto.graphics.line(i * 100, -1000, i * 100, 1000)
+        pesto.graphics.line(-1000, i * 100, 1000, i * 100)
+    end
+
+    for _, marker in ipairs(markers) do
+        pesto.graphics.circle("fill", marker.x, marker.y, 10)
+    end
+
+    pesto.graphics.resetCamera()
+    pesto.graphics.print("Markers: " .. #markers, 10, 40)
+end
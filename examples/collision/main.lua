@@ -0,0 +1,47 @@
+-- Move the box with the arrow keys, it slides along the walls instead of
+-- going through them. check tells where a move would end without doing it
+local world = pesto.collision.newWorld(64)
+
+local player = { x = 100, y = 100, w = 32, h = 32 }
+local walls = {
+    { x = 0, y = 0, w = 800, h = 20 },
+    { x = 0, y = 580, w = 800, h = 20 },
+    { x = 0, y = 0, w = 20, h = 600 },
+    { x = 780, y = 0, w = 20, h = 600 },
+    { x = 300, y = 200, w = 200, h = 40 },
+}
+
+world:add(player, player.x, player.y, player.w, player.h)
+
+for _, wall in ipairs(walls) do
+    world:add(wall, wall.x, wall.y, wall.w, wall.h)
+end
+
+local touching = 0
+
+function pesto.update(dt)
+    local dx, dy = 0, 0
+
+    if pesto.keyboard.isDown("left") then dx = dx - 300 * dt end
+    if pesto.keyboard.isDown("right") then dx = dx + 300 * dt end
+    if pesto.keyboard.isDown("up") then dy = dy - 300 * dt end
+    if pesto.keyboard.isDown("down") then dy = dy + 300 * dt end
+
+    local _, _, collisions, count = world:check(player, player.x + dx, player.y + dy)
+    touching = count
+
+    for i = 1, count do
+        assert(collisions[i].other ~= player)
+    end
+
+    player.x, player.y = world:move(player, player.x + dx, player.y + dy)
+end
+
+function pesto.draw()
+    for _, wall in ipairs(walls) do
+        pesto.graphics.rectangle("fill", wall.x, wall.y, wall.w, wall.h)
+    end
+
+    pesto.graphics.rectangle("line", player.x, player.y, player.w, player.h)
+    pesto.graphics.print("Touching: " .. touching, 30, 60)
+end
@@ -0,0 +1,8 @@
+-- Formatting and linting skip the files next to this one that have no
+-- extension, run with --format to check it doesn't crash
+function pesto.update()
+end
+
+function pesto.draw()
+    pesto.graphics.print("Formatted without crashing", 10, 40)
+end
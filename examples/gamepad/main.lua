@@ -0,0 +1,24 @@
+-- Move the square with the left stick of the first gamepad, hold a to go faster
+local x = 640
+local y = 360
+
+function pesto.update(dt)
+    local speed = pesto.gamepad.isDown(1, "a") and 800 or 400
+
+    local dx = pesto.gamepad.getAxis(1, "leftx")
+    local dy = pesto.gamepad.getAxis(1, "lefty")
+
+    -- Ignore small movements around the center of the stick
+    if math.abs(dx) > 0.2 then
+        x = x + dx * speed * dt
+    end
+
+    if math.abs(dy) > 0.2 then
+        y = y + dy * speed * dt
+    end
+end
+
+function pesto.draw()
+    pesto.graphics.rectangle("fill", x - 20, y - 20, 40, 40)
+    pesto.graphics.print("Gamepads: " .. pesto.gamepad.getCount(), 10, 40)
+end
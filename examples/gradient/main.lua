@@ -0,0 +1,13 @@
+-- A sky fading into the horizon and a health bar shrinking from green to red
+local health = 1
+
+function pesto.update(dt)
+    health = (health - dt * 0.2) % 1
+end
+
+function pesto.draw()
+    pesto.graphics.rectangleGradient(0, 0, 800, 600, { 0.1, 0.2, 0.5 }, { 0.9, 0.6, 0.4 })
+
+    pesto.graphics.rectangle("fill", 20, 20, 204, 24)
+    pesto.graphics.rectangleGradient(22, 22, 200 * health, 20, pesto.color.RED, pesto.color.GREEN, "horizontal")
+end
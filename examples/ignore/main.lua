@@ -0,0 +1,10 @@
+-- Run with --format, vendor/greet.lua stays minified because it's
+-- listed in .pestoignore
+local greet = require("vendor.greet")
+
+function pesto.update()
+end
+
+function pesto.draw()
+    pesto.graphics.print(greet.greet("pesto"), 10, 40)
+end
@@ -0,0 +1,23 @@
+-- The simulation only runs 10 times a second, the top box jumps between
+-- updates while the bottom one is drawn interpolated with alpha
+pesto.timer.setFixedStep(1 / 10)
+
+local previous = 0
+local x = 0
+
+function pesto.update(dt)
+    previous = x
+    x = (x + 200 * dt) % 800
+end
+
+function pesto.draw(alpha)
+    pesto.graphics.rectangle("fill", x, 200, 40, 40)
+
+    -- Wrapping around would lerp backwards across the screen
+    local drawn = x
+    if x >= previous then
+        drawn = previous + (x - previous) * alpha
+    end
+
+    pesto.graphics.rectangle("fill", drawn, 300, 40, 40)
+end
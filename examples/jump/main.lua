@@ -0,0 +1,19 @@
+-- Press space to jump, holding it down does not jump again
+local y = 600
+local velocity = 0
+local jumps = 0
+
+function pesto.update(dt)
+    if pesto.keyboard.isPressed("space") and y >= 600 then
+        velocity = -900
+        jumps = jumps + 1
+    end
+
+    velocity = velocity + 2000 * dt
+    y = math.min(y + velocity * dt, 600)
+end
+
+function pesto.draw()
+    pesto.graphics.rectangle("fill", 620, y - 40, 40, 40)
+    pesto.graphics.print("Jumps: " .. jumps, 10, 40)
+end
@@ -0,0 +1,28 @@
+-- Entities are drawn in whatever order they were spawned in, but their
+-- layers keep the shadows under the bodies and the labels over everything
+local entities = {}
+
+for i = 1, 20 do
+    table.insert(entities, { x = math.random(50, 750), y = math.random(50, 550) })
+end
+
+function pesto.draw()
+    pesto.graphics.print("Drawn without a layer, under everything", 10, 40)
+
+    for i, entity in ipairs(entities) do
+        pesto.graphics.setLayer(3)
+        pesto.graphics.setColor(1, 1, 1)
+        pesto.graphics.print(i, entity.x - 8, entity.y - 40)
+
+        pesto.graphics.setLayer(2)
+        pesto.graphics.setColor(0.2, 0.6, 1)
+        pesto.graphics.circle("fill", entity.x, entity.y, 20)
+
+        pesto.graphics.setLayer(1)
+        pesto.graphics.setColor(0, 0, 0)
+        pesto.graphics.circle("fill", entity.x + 8, entity.y + 8, 20)
+    end
+
+    pesto.graphics.setLayer()
+    pesto.graphics.setColor(1, 1, 1)
+end
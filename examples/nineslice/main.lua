@@ -0,0 +1,10 @@
+-- The panel follows the mouse from the top left corner, shrinking it below
+-- the 16 pixel borders squeezes the corners instead of overlapping them
+local panel = pesto.graphics.newNineSlice(pesto.graphics.newImage("panel.png"), 8, 8, 8, 8)
+
+function pesto.draw()
+    local x, y = pesto.mouse.getPosition()
+
+    pesto.graphics.drawNineSlice(panel, 100, 100, x - 100, y - 100)
+    pesto.graphics.drawNineSlice(panel, 20, 20, 10, 10)
+end
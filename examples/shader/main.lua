@@ -0,0 +1,58 @@
+-- A wave shader that bends everything drawn while it is active
+local vertex = [[
+#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+uniform float time;
+
+void main() {
+    vec3 wave = position + vec3(0.0, sin(position.x * 0.02 + time * 4.0) * 20.0, 0.0);
+    gl_Position = Projection * Model * vec4(wave, 1.0);
+    uv = texcoord;
+    color = color0 / 255.0;
+}
+]]
+
+local fragment = [[
+#version 100
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform sampler2D Texture;
+uniform lowp vec3 tint;
+
+void main() {
+    gl_FragColor = color * texture2D(Texture, uv) * vec4(tint, 1.0);
+}
+]]
+
+local shader
+local time = 0
+
+function pesto.load()
+    shader = pesto.graphics.newShader(vertex, fragment)
+end
+
+function pesto.update(dt)
+    time = time + dt
+    pesto.graphics.send(shader, "time", time)
+    pesto.graphics.send(shader, "tint", {1, 0.5 + math.sin(time) * 0.5, 1})
+end
+
+function pesto.draw()
+    pesto.graphics.setShader(shader)
+
+    for x = 0, 1280, 40 do
+        pesto.graphics.rectangle("fill", x, 340, 30, 40)
+    end
+
+    pesto.graphics.setShader()
+    pesto.graphics.print("Only the bars are drawn with the shader", 10, 40)
+end
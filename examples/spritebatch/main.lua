@@ -0,0 +1,51 @@
+-- Draws 10000 moving sprites, press space to switch between one draw call
+-- per sprite and a sprite batch and compare the fps
+local COUNT = 10000
+
+local image
+local batch
+local sprites = {}
+local batched = true
+
+function pesto.load()
+    image = pesto.graphics.newImage("dot.png")
+    batch = pesto.graphics.newSpriteBatch(image)
+
+    for _ = 1, COUNT do
+        table.insert(sprites, {
+            x = math.random() * 1280,
+            y = math.random() * 720,
+            dx = math.random() * 200 - 100,
+            dy = math.random() * 200 - 100,
+        })
+    end
+end
+
+function pesto.update(dt)
+    if pesto.keyboard.isPressed("space") then
+        batched = not batched
+    end
+
+    for _, sprite in ipairs(sprites) do
+        sprite.x = (sprite.x + sprite.dx * dt) % 1280
+        sprite.y = (sprite.y + sprite.dy * dt) % 720
+    end
+end
+
+function pesto.draw()
+    if batched then
+        batch:clear()
+
+        for _, sprite in ipairs(sprites) do
+            batch:add(sprite.x, sprite.y)
+        end
+
+        batch:draw()
+    else
+        for _, sprite in ipairs(sprites) do
+            pesto.graphics.draw(image, sprite.x, sprite.y)
+        end
+    end
+
+    pesto.graphics.print((batched and "Sprite batch" or "Individual draws") .. ", FPS: " .. pesto.timer.getFPS(), 10, 40)
+end
@@ -0,0 +1,35 @@
+-- A spotlight following the mouse reveals the grid behind it, and the ring
+-- around it is drawn where two overlapping circles incremented the mask
+local time = 0
+
+function pesto.update(dt)
+    time = time + dt
+end
+
+function pesto.draw()
+    local x, y = pesto.mouse.getPosition()
+
+    pesto.graphics.stencil(function()
+        pesto.graphics.circle("fill", x, y, 120)
+    end)
+
+    pesto.graphics.stencil(function()
+        pesto.graphics.circle("fill", 200 + math.sin(time) * 60, 300, 100)
+        pesto.graphics.circle("fill", 200 - math.sin(time) * 60, 300, 100)
+    end, "increment", 1, true)
+
+    pesto.graphics.setStencilTest("equal", 1)
+
+    for i = 0, 40 do
+        pesto.graphics.line(i * 20, 0, i * 20, 600)
+        pesto.graphics.line(0, i * 20, 800, i * 20)
+    end
+
+    pesto.graphics.setStencilTest("gequal", 2)
+    pesto.graphics.setColor(1, 0.4, 0.2)
+    pesto.graphics.rectangle("fill", 0, 0, 800, 600)
+    pesto.graphics.setColor(1, 1, 1)
+
+    pesto.graphics.setStencilTest()
+    pesto.graphics.print("Move the mouse around", 10, 40)
+end
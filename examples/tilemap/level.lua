@@ -0,0 +1,80 @@
+return {
+  version = "1.10",
+  luaversion = "5.1",
+  orientation = "orthogonal",
+  renderorder = "right-down",
+  width = 10,
+  height = 8,
+  tilewidth = 16,
+  tileheight = 16,
+  tilesets = {
+    {
+      name = "tiles",
+      firstgid = 1,
+      tilewidth = 16,
+      tileheight = 16,
+      spacing = 0,
+      margin = 0,
+      columns = 2,
+      image = "tiles.png",
+      imagewidth = 32,
+      imageheight = 16,
+      tilecount = 2
+    }
+  },
+  layers = {
+    {
+      type = "tilelayer",
+      name = "ground",
+      width = 10,
+      height = 8,
+      visible = true,
+      opacity = 1,
+      offsetx = 0,
+      offsety = 0,
+      encoding = "lua",
+      data = {
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1,
+        1,1,1,1,1,1,1,1,1,1
+      }
+    },
+    {
+      type = "tilelayer",
+      name = "walls",
+      width = 10,
+      height = 8,
+      visible = true,
+      opacity = 1,
+      offsetx = 0,
+      offsety = 0,
+      encoding = "lua",
+      data = {
+        2,2,2,2,2,2,2,2,2,2,
+        2,0,0,0,0,0,0,0,0,2,
+        2,0,0,0,0,0,0,0,0,2,
+        2,0,0,0,0,0,0,0,0,2,
+        2,0,0,0,0,0,0,0,0,2,
+        2,0,0,0,0,0,0,0,0,2,
+        2,0,0,0,0,0,0,0,0,2,
+        2,2,2,2,2,2,2,2,2,2
+      }
+    },
+    {
+      type = "objectgroup",
+      name = "spawns",
+      visible = true,
+      opacity = 1,
+      offsetx = 0,
+      offsety = 0,
+      objects = {
+        { id = 1, name = "player", type = "", shape = "point", x = 40, y = 40, width = 0, height = 0, rotation = 0, visible = true, properties = {} }
+      }
+    }
+  }
+}
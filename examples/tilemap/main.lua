@@ -0,0 +1,16 @@
+-- Draws a level exported from Tiled as Lua, scaled up, with the player
+-- spawn point read from its object layer
+local map = pesto.tilemap.load("level.lua")
+local spawn = map.objectgroups[1].objects[1]
+
+function pesto.update() end
+
+function pesto.draw()
+    pesto.graphics.push()
+    pesto.graphics.scale(4)
+
+    map:draw()
+    pesto.graphics.circle("fill", spawn.x, spawn.y, 4)
+
+    pesto.graphics.pop()
+end
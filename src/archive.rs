@@ -0,0 +1,188 @@
+// Packaged games are a copy of the pesto executable with the project
+// appended to it:
+//
+//   [pesto executable][zip of the project][zip size, u64 le]["PESTOPAK"]
+//
+// Paths inside the zip are relative to the project directory and always
+// use forward slashes. Hidden files and folders are left out.
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    path::{Component, Path},
+};
+use walkdir::WalkDir;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+const MAGIC: &[u8; 8] = b"PESTOPAK";
+const TRAILER_SIZE: usize = 16;
+
+// Project files loaded from an archive
+pub struct Archive {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl Archive {
+    pub fn from_zip(bytes: &[u8]) -> Result<Self, String> {
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).map_err(|err| err.to_string())?;
+        let mut files = HashMap::new();
+
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(|err| err.to_string())?;
+
+            if !file.is_file() {
+                continue;
+            }
+
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)
+                .map_err(|err| err.to_string())?;
+
+            files.insert(file.name().to_string(), contents);
+        }
+
+        Ok(Archive { files })
+    }
+
+    // The archive appended to the running executable, if there is one. Only
+    // the trailer and the zip are read, not the whole program
+    pub fn from_executable() -> Option<Self> {
+        let mut executable = File::open(env::current_exe().ok()?).ok()?;
+
+        let length = executable.seek(SeekFrom::End(0)).ok()?;
+
+        if length < TRAILER_SIZE as u64 {
+            return None;
+        }
+
+        let mut trailer = [0; TRAILER_SIZE];
+        executable
+            .seek(SeekFrom::Start(length - TRAILER_SIZE as u64))
+            .ok()?;
+        executable.read_exact(&mut trailer).ok()?;
+
+        if !trailer.ends_with(MAGIC) {
+            return None;
+        }
+
+        let size = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+
+        if size > length - TRAILER_SIZE as u64 {
+            return None;
+        }
+
+        let mut zip = vec![0; size as usize];
+        executable
+            .seek(SeekFrom::Start(length - TRAILER_SIZE as u64 - size))
+            .ok()?;
+        executable.read_exact(&mut zip).ok()?;
+
+        match Archive::from_zip(&zip) {
+            Ok(archive) => Some(archive),
+            Err(err) => {
+                println!("Could not read the packaged game: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(|path| path.as_str())
+    }
+
+    pub fn read(&self, path: &str) -> Option<&[u8]> {
+        self.files
+            .get(path.trim_start_matches("./"))
+            .map(|contents| contents.as_slice())
+    }
+}
+
+// Split an executable into the program and the appended zip
+fn split_executable(bytes: &[u8]) -> (&[u8], Option<&[u8]>) {
+    if bytes.len() < TRAILER_SIZE || !bytes.ends_with(MAGIC) {
+        return (bytes, None);
+    }
+
+    let trailer = bytes.len() - TRAILER_SIZE;
+    let size = u64::from_le_bytes(bytes[trailer..trailer + 8].try_into().unwrap()) as usize;
+
+    if size > trailer {
+        return (bytes, None);
+    }
+
+    (
+        &bytes[..trailer - size],
+        Some(&bytes[trailer - size..trailer]),
+    )
+}
+
+// Zip every file of the project except the output itself, which can end up
+// inside the project when packaging again
+fn zip_project(directory: &Path, output: &Path) -> Result<Vec<u8>, String> {
+    let output = fs::canonicalize(output).ok();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let entries = WalkDir::new(directory)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| output.is_none() || fs::canonicalize(entry.path()).ok() != output);
+
+    for entry in entries {
+        let relative = entry.path().strip_prefix(directory).unwrap();
+
+        let name = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let contents = fs::read(entry.path()).map_err(|err| err.to_string())?;
+
+        zip.start_file(name, options)
+            .map_err(|err| err.to_string())?;
+        zip.write_all(&contents).map_err(|err| err.to_string())?;
+    }
+
+    Ok(zip.finish().map_err(|err| err.to_string())?.into_inner())
+}
+
+// Write a copy of pesto with the project appended to it, main is the entry
+// file relative to the project
+pub fn package(directory: &str, main: &str, output: &Path) -> Result<(), String> {
+    let directory = Path::new(directory);
+
+    if !directory.join(main).exists() {
+        return Err(format!("{} not found in '{}'.", main, directory.display()));
+    }
+
+    let executable_path = env::current_exe().map_err(|err| err.to_string())?;
+    let executable = fs::read(&executable_path).map_err(|err| err.to_string())?;
+
+    // Packaging from a packaged game replaces its project
+    let (program, _) = split_executable(&executable);
+
+    let zip = zip_project(directory, output)?;
+
+    let mut packaged = program.to_vec();
+    packaged.extend_from_slice(&zip);
+    packaged.extend_from_slice(&(zip.len() as u64).to_le_bytes());
+    packaged.extend_from_slice(MAGIC);
+
+    fs::write(output, packaged).map_err(|err| err.to_string())?;
+
+    // Keep the executable bit on unix
+    if let Ok(metadata) = fs::metadata(&executable_path) {
+        fs::set_permissions(output, metadata.permissions()).map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
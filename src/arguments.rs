@@ -0,0 +1,56 @@
+use mlua::prelude::*;
+
+// Create an api function like lua.create_function, but arguments that fail
+// to convert are reported with the function name and argument position,
+// for example "pesto.graphics.circle: argument 1 must be a number, got string"
+pub fn function<'lua, A, R, F>(
+    lua: &'lua Lua,
+    name: &'static str,
+    func: F,
+) -> LuaResult<LuaFunction<'lua>>
+where
+    A: FromLuaMulti<'lua>,
+    R: IntoLuaMulti<'lua>,
+    F: Fn(&'lua Lua, A) -> LuaResult<R> + 'static,
+{
+    lua.create_function(move |lua, args: LuaMultiValue<'lua>| {
+        let args = A::from_lua_args(args, 1, None, lua).map_err(|err| argument_error(name, err))?;
+
+        func(lua, args)
+    })
+}
+
+// What a rust type is called in lua
+fn lua_type(rust_type: &str) -> &str {
+    match rust_type {
+        "f32" | "f64" => "a number",
+        "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => {
+            "an integer"
+        }
+        "String" | "string" => "a string",
+        "bool" | "boolean" => "a boolean",
+        "table" => "a table",
+        "function" => "a function",
+        "userdata" => "an object",
+        rust_type => rust_type,
+    }
+}
+
+fn argument_error(name: &str, err: LuaError) -> LuaError {
+    let LuaError::BadArgument { pos, cause, .. } = &err else {
+        return err;
+    };
+
+    let message = match cause.as_ref() {
+        // Lua itself doesn't tell integers and other numbers apart
+        LuaError::FromLuaConversionError { from, to, .. } => format!(
+            "must be {}, got {}",
+            lua_type(to),
+            if *from == "integer" { "number" } else { from }
+        ),
+        LuaError::UserDataTypeMismatch => "is the wrong type of object".to_string(),
+        cause => cause.to_string(),
+    };
+
+    LuaError::RuntimeError(format!("{}: argument {} {}", name, pos, message))
+}
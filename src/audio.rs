@@ -0,0 +1,393 @@
+use crate::{arguments, project::Project};
+use macroquad::{
+    audio::{
+        load_sound_from_bytes, play_sound, set_sound_volume, stop_sound, PlaySoundParams, Sound,
+    },
+    time::get_time,
+};
+use mlua::prelude::*;
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+// A loaded sound and how it's being played
+struct Source {
+    sound: Sound,
+    volume: f32,
+    looped: bool,
+    status: Status,
+    // Length in seconds read from the file, None when it couldn't be found
+    duration: Option<f64>,
+    started: f64,
+}
+
+impl Source {
+    // macroquad can't pause or seek sounds, so a paused source keeps playing
+    // silently and picks up wherever it got to when resumed
+    fn apply_volume(&self, gain: f32) {
+        let volume = match self.status {
+            Status::Paused => 0.0,
+            _ => self.volume * gain,
+        };
+
+        set_sound_volume(&self.sound, volume);
+    }
+
+    fn stop(&mut self) {
+        stop_sound(&self.sound);
+        self.status = Status::Stopped;
+    }
+
+    // macroquad doesn't report when a sound ends, so a source that doesn't
+    // loop is over once its length has passed since it was played
+    fn finished(&self) -> bool {
+        !self.looped
+            && self
+                .duration
+                .is_some_and(|duration| get_time() - self.started >= duration)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// Length in seconds of a wav or ogg vorbis file, the formats macroquad can
+// decode, read from the headers without decoding the samples
+fn sound_duration(bytes: &[u8]) -> Option<f64> {
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE") {
+        let mut byte_rate = None;
+        let mut offset = 12;
+
+        // Chunks are an id and a size, padded to an even length
+        while offset + 8 <= bytes.len() {
+            let id = &bytes[offset..offset + 4];
+            let size = read_u32(bytes, offset + 4)? as usize;
+
+            match id {
+                b"fmt " => byte_rate = read_u32(bytes, offset + 16),
+                b"data" => return Some(size as f64 / byte_rate.filter(|rate| *rate > 0)? as f64),
+                _ => {}
+            }
+
+            offset += 8 + size + size % 2;
+        }
+
+        None
+    } else if bytes.starts_with(b"OggS") {
+        // The identification header has the sample rate and the last page
+        // the total number of samples as its granule position
+        let header = bytes
+            .windows(7)
+            .position(|window| window == b"\x01vorbis")?;
+        let sample_rate = read_u32(bytes, header + 12).filter(|rate| *rate > 0)?;
+
+        let last_page = bytes.windows(4).rposition(|window| window == b"OggS")?;
+        let samples =
+            u64::from_le_bytes(bytes.get(last_page + 6..last_page + 14)?.try_into().ok()?);
+
+        Some(samples as f64 / sample_rate as f64)
+    } else {
+        None
+    }
+}
+
+// Lua handle for a source
+#[derive(Clone)]
+struct SourceHandle(Rc<RefCell<Source>>);
+
+impl LuaUserData for SourceHandle {}
+
+// Rust side audio state, macroquad plays sounds fire and forget so the
+// sources that were started are tracked here
+pub struct AudioState {
+    playing: Vec<Rc<RefCell<Source>>>,
+    master_volume: f32,
+    muted: bool,
+}
+
+impl Default for AudioState {
+    fn default() -> Self {
+        AudioState {
+            playing: Vec::new(),
+            master_volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl AudioState {
+    // Master volume multiplied into every source
+    fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master_volume
+        }
+    }
+
+    // Update the sources already playing after the gain changed
+    fn apply_gain(&self) {
+        for source in &self.playing {
+            source.borrow().apply_volume(self.gain());
+        }
+    }
+
+    // Called before reloading so the old project's sounds don't keep playing
+    pub fn stop_all(&mut self) {
+        for source in self.playing.drain(..) {
+            source.borrow_mut().stop();
+        }
+    }
+}
+
+// Sound loading is async in macroquad but finishes right away outside of
+// the web, so the future is polled once instead of waiting for a frame
+fn load_sound_now(bytes: &[u8]) -> Option<Sound> {
+    let future = pin!(load_sound_from_bytes(bytes));
+
+    match future.poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(result) => result.ok(),
+        Poll::Pending => None,
+    }
+}
+
+fn load_source(project: &Project, path: &str, looped: bool) -> LuaResult<SourceHandle> {
+    let bytes = project.read(path).map_err(|err| {
+        LuaError::RuntimeError(format!("Could not read sound '{}': {}", path, err))
+    })?;
+
+    let sound = load_sound_now(&bytes)
+        .ok_or_else(|| LuaError::RuntimeError(format!("Could not decode sound '{}'.", path)))?;
+
+    Ok(SourceHandle(Rc::new(RefCell::new(Source {
+        sound,
+        volume: 1.0,
+        looped,
+        status: Status::Stopped,
+        duration: sound_duration(&bytes),
+        started: 0.0,
+    }))))
+}
+
+pub fn register(
+    lua: &Lua,
+    pesto_table: &LuaTable,
+    project: &Rc<Project>,
+    audio_state: &Rc<RefCell<AudioState>>,
+) {
+    let audio_table = lua.create_table().unwrap();
+
+    let sound_project = project.clone();
+    let audio_new_source =
+        arguments::function(lua, "pesto.audio.newSource", move |_, path: String| {
+            load_source(&sound_project, &path, false)
+        })
+        .unwrap();
+
+    // Music is fully decoded like any other sound, it just loops by default
+    let music_project = project.clone();
+    let audio_new_music =
+        arguments::function(lua, "pesto.audio.newMusic", move |_, path: String| {
+            load_source(&music_project, &path, true)
+        })
+        .unwrap();
+
+    // Playing a source again restarts it
+    let state = audio_state.clone();
+    let audio_play = arguments::function(
+        lua,
+        "pesto.audio.play",
+        move |_, (source, looped): (LuaUserDataRef<SourceHandle>, Option<bool>)| {
+            let mut state = state.borrow_mut();
+            let mut data = source.0.borrow_mut();
+
+            if data.status != Status::Stopped {
+                data.stop();
+            }
+
+            if let Some(looped) = looped {
+                data.looped = looped;
+            }
+
+            play_sound(
+                &data.sound,
+                PlaySoundParams {
+                    looped: data.looped,
+                    volume: data.volume * state.gain(),
+                },
+            );
+
+            data.status = Status::Playing;
+            data.started = get_time();
+
+            if !state
+                .playing
+                .iter()
+                .any(|other| Rc::ptr_eq(other, &source.0))
+            {
+                state.playing.push(source.0.clone());
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // macroquad can't pause sounds, so pausing silences the source while
+    // it keeps playing, and resuming makes it heard again from wherever it
+    // got to in the meantime
+    let state = audio_state.clone();
+    let audio_pause = arguments::function(
+        lua,
+        "pesto.audio.pause",
+        move |_, source: LuaUserDataRef<SourceHandle>| {
+            let mut data = source.0.borrow_mut();
+
+            if data.status == Status::Playing {
+                data.status = Status::Paused;
+                data.apply_volume(state.borrow().gain());
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = audio_state.clone();
+    let audio_resume = arguments::function(
+        lua,
+        "pesto.audio.resume",
+        move |_, source: LuaUserDataRef<SourceHandle>| {
+            let mut data = source.0.borrow_mut();
+
+            if data.status == Status::Paused {
+                data.status = if data.finished() {
+                    Status::Stopped
+                } else {
+                    Status::Playing
+                };
+                data.apply_volume(state.borrow().gain());
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = audio_state.clone();
+    let audio_stop = arguments::function(
+        lua,
+        "pesto.audio.stop",
+        move |_, source: LuaUserDataRef<SourceHandle>| {
+            source.0.borrow_mut().stop();
+
+            state
+                .borrow_mut()
+                .playing
+                .retain(|other| !Rc::ptr_eq(other, &source.0));
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Sounds whose length couldn't be read count as playing until they are
+    // stopped
+    let audio_is_playing = arguments::function(
+        lua,
+        "pesto.audio.isPlaying",
+        |_, source: LuaUserDataRef<SourceHandle>| {
+            let data = source.0.borrow();
+
+            Ok(data.status == Status::Playing && !data.finished())
+        },
+    )
+    .unwrap();
+
+    // setVolume(volume) sets the master volume, setVolume(source, volume)
+    // the volume of a single source
+    let state = audio_state.clone();
+    let audio_set_volume = arguments::function(
+        lua,
+        "pesto.audio.setVolume",
+        move |lua, args: LuaMultiValue| {
+            let mut state = state.borrow_mut();
+
+            if let Some(LuaValue::UserData(_)) = args.iter().next() {
+                let (source, volume): (LuaUserDataRef<SourceHandle>, f32) =
+                    lua.unpack_multi(args)?;
+                let mut data = source.0.borrow_mut();
+
+                data.volume = volume.clamp(0.0, 1.0);
+                data.apply_volume(state.gain());
+            } else {
+                let volume: f32 = lua.unpack_multi(args)?;
+
+                state.master_volume = volume.clamp(0.0, 1.0);
+                state.apply_gain();
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = audio_state.clone();
+    let audio_get_volume = arguments::function(
+        lua,
+        "pesto.audio.getVolume",
+        move |_, source: Option<LuaUserDataRef<SourceHandle>>| {
+            Ok(match source {
+                Some(source) => source.0.borrow().volume,
+                None => state.borrow().master_volume,
+            })
+        },
+    )
+    .unwrap();
+
+    let state = audio_state.clone();
+    let audio_set_muted =
+        arguments::function(lua, "pesto.audio.setMuted", move |_, muted: bool| {
+            let mut state = state.borrow_mut();
+
+            state.muted = muted;
+            state.apply_gain();
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = audio_state.clone();
+    let audio_is_muted = arguments::function(lua, "pesto.audio.isMuted", move |_, ()| {
+        Ok(state.borrow().muted)
+    })
+    .unwrap();
+
+    audio_table.set("newSource", audio_new_source).unwrap();
+    audio_table.set("newMusic", audio_new_music).unwrap();
+    audio_table.set("play", audio_play).unwrap();
+    audio_table.set("pause", audio_pause).unwrap();
+    audio_table.set("resume", audio_resume).unwrap();
+    audio_table.set("stop", audio_stop).unwrap();
+    audio_table.set("isPlaying", audio_is_playing).unwrap();
+    audio_table.set("setVolume", audio_set_volume).unwrap();
+    audio_table.set("getVolume", audio_get_volume).unwrap();
+    audio_table.set("setMuted", audio_set_muted).unwrap();
+    audio_table.set("isMuted", audio_is_muted).unwrap();
+
+    pesto_table.set("audio", audio_table).unwrap();
+}
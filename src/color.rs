@@ -0,0 +1,94 @@
+use macroquad::prelude::*;
+use mlua::prelude::*;
+
+// Named colors, the same as macroquad's constants
+const COLORS: &[(&str, Color)] = &[
+    ("LIGHTGRAY", LIGHTGRAY),
+    ("GRAY", GRAY),
+    ("DARKGRAY", DARKGRAY),
+    ("YELLOW", YELLOW),
+    ("GOLD", GOLD),
+    ("ORANGE", ORANGE),
+    ("PINK", PINK),
+    ("RED", RED),
+    ("MAROON", MAROON),
+    ("GREEN", GREEN),
+    ("LIME", LIME),
+    ("DARKGREEN", DARKGREEN),
+    ("SKYBLUE", SKYBLUE),
+    ("BLUE", BLUE),
+    ("DARKBLUE", DARKBLUE),
+    ("PURPLE", PURPLE),
+    ("VIOLET", VIOLET),
+    ("DARKPURPLE", DARKPURPLE),
+    ("BEIGE", BEIGE),
+    ("BROWN", BROWN),
+    ("DARKBROWN", DARKBROWN),
+    ("WHITE", WHITE),
+    ("BLACK", BLACK),
+    ("BLANK", BLANK),
+    ("MAGENTA", MAGENTA),
+];
+
+// Colors are passed to lua as { r, g, b, a } tables
+pub fn new_color<'lua>(lua: &'lua Lua, color: Color) -> LuaResult<LuaTable<'lua>> {
+    lua.create_sequence_from([color.r, color.g, color.b, color.a])
+}
+
+// Read a color given either as a { r, g, b, a } table or as separate
+// numbers, the alpha defaults to 1 in both cases
+pub fn parse_color(lua: &Lua, args: LuaMultiValue) -> LuaResult<Color> {
+    let (r, g, b, a): (f32, f32, f32, Option<f32>) = match args.iter().next() {
+        Some(LuaValue::Table(table)) => {
+            (table.get(1)?, table.get(2)?, table.get(3)?, table.get(4)?)
+        }
+        _ => lua.unpack_multi(args)?,
+    };
+
+    Ok(Color::new(r, g, b, a.unwrap_or(1.0)))
+}
+
+// Hue in degrees, wrapping around, saturation and value between 0 and 1
+pub fn hsv(h: f32, s: f32, v: f32) -> (f32, f32, f32) {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let s = s.clamp(0.0, 1.0);
+    let v = v.clamp(0.0, 1.0);
+
+    let chroma = v * s;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = v - chroma;
+
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    (r + m, g + m, b + m)
+}
+
+// HSL is HSV with the value and saturation derived from the lightness
+pub fn hsl(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    let s = s.clamp(0.0, 1.0);
+    let l = l.clamp(0.0, 1.0);
+
+    let v = l + s * l.min(1.0 - l);
+    let saturation = if v == 0.0 { 0.0 } else { 2.0 * (1.0 - l / v) };
+
+    hsv(h, saturation, v)
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable) {
+    let color_table = lua.create_table().unwrap();
+
+    for (name, color) in COLORS {
+        color_table
+            .set(*name, new_color(lua, *color).unwrap())
+            .unwrap();
+    }
+
+    pesto_table.set("color", color_table).unwrap();
+}
@@ -0,0 +1,43 @@
+use crate::arguments;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use mlua::prelude::*;
+use std::io::{Read, Write};
+
+// Data is compressed to the zlib format (RFC 1950), a standard that won't
+// change between pesto versions and that other tools can read too. Like
+// hash, these are added to pesto.utils after lume is loaded
+pub fn register(lua: &Lua, pesto_table: &LuaTable) {
+    let utils_table: LuaTable = pesto_table.get("utils").unwrap();
+
+    let utils_compress =
+        arguments::function(lua, "pesto.utils.compress", |lua, data: LuaString| {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+            let compressed = encoder
+                .write_all(data.as_bytes())
+                .and_then(|()| encoder.finish())
+                .map_err(|err| {
+                    LuaError::RuntimeError(format!("Could not compress data: {}", err))
+                })?;
+
+            lua.create_string(compressed)
+        })
+        .unwrap();
+
+    let utils_decompress =
+        arguments::function(lua, "pesto.utils.decompress", |lua, data: LuaString| {
+            let mut decompressed = Vec::new();
+
+            ZlibDecoder::new(data.as_bytes())
+                .read_to_end(&mut decompressed)
+                .map_err(|err| {
+                    LuaError::RuntimeError(format!("Could not decompress data: {}", err))
+                })?;
+
+            lua.create_string(decompressed)
+        })
+        .unwrap();
+
+    utils_table.set("compress", utils_compress).unwrap();
+    utils_table.set("decompress", utils_decompress).unwrap();
+}
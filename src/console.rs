@@ -0,0 +1,101 @@
+use crate::arguments;
+use macroquad::prelude::*;
+use mlua::{prelude::*, Variadic};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+// Lines kept in the scrollback
+const MAX_LINES: usize = 500;
+
+const FONT_SIZE: f32 = 20.0;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.25;
+
+// Output of print, shown over the game while the console is open. The
+// scrollback survives reloads so prints leading up to an error stay visible
+#[derive(Default)]
+pub struct ConsoleState {
+    lines: VecDeque<String>,
+    visible: bool,
+    scroll: usize,
+}
+
+impl ConsoleState {
+    fn push(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.lines.len() == MAX_LINES {
+                self.lines.pop_front();
+            }
+
+            self.lines.push_back(line.to_string());
+        }
+    }
+}
+
+// Toggle the console with the backtick key and scroll it with the wheel,
+// then draw it over the top half of the virtual resolution
+pub fn update_and_draw(state: &mut ConsoleState, virtual_size: Vec2) {
+    if is_key_pressed(KeyCode::GraveAccent) {
+        state.visible = !state.visible;
+        state.scroll = 0;
+    }
+
+    if !state.visible {
+        return;
+    }
+
+    let height = virtual_size.y * 0.5;
+    let visible_lines = ((height - 10.0) / LINE_HEIGHT) as usize;
+    let max_scroll = state.lines.len().saturating_sub(visible_lines);
+
+    let wheel = mouse_wheel().1;
+
+    if wheel > 0.0 {
+        state.scroll = (state.scroll + 3).min(max_scroll);
+    } else if wheel < 0.0 {
+        state.scroll = state.scroll.saturating_sub(3);
+    }
+
+    draw_rectangle(
+        0.0,
+        0.0,
+        virtual_size.x,
+        height,
+        Color::new(0.0, 0.0, 0.0, 0.8),
+    );
+
+    // Newest lines at the bottom, scrolling moves back in time
+    let end = state.lines.len() - state.scroll.min(max_scroll);
+    let start = end.saturating_sub(visible_lines);
+
+    for (i, line) in state.lines.range(start..end).enumerate() {
+        draw_text(
+            line,
+            10.0,
+            5.0 + (i + 1) as f32 * LINE_HEIGHT,
+            FONT_SIZE,
+            WHITE,
+        );
+    }
+}
+
+// Replace the global print so its output also reaches the console
+pub fn register(lua: &Lua, console_state: &Rc<RefCell<ConsoleState>>) {
+    let state = console_state.clone();
+    let print = arguments::function(lua, "print", move |lua, values: Variadic<LuaValue>| {
+        let tostring: LuaFunction = lua.globals().get("tostring")?;
+
+        let parts = values
+            .into_iter()
+            .map(|value| tostring.call::<_, String>(value))
+            .collect::<LuaResult<Vec<String>>>()?;
+
+        let text = parts.join("\t");
+
+        println!("{}", text);
+        state.borrow_mut().push(&text);
+
+        Ok(())
+    })
+    .unwrap();
+
+    lua.globals().set("print", print).unwrap();
+}
@@ -0,0 +1,201 @@
+use crate::arguments;
+use mlua::prelude::*;
+
+// Standard base64 alphabet (RFC 4648), encoded data is padded with '='
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Both cases of hex digits are accepted
+fn decode_hex(text: &[u8]) -> LuaResult<Vec<u8>> {
+    let invalid = || LuaError::RuntimeError("Invalid hex data.".to_string());
+
+    if !text.len().is_multiple_of(2) || !text.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid());
+    }
+
+    text.chunks(2)
+        .map(|pair| {
+            let digits = std::str::from_utf8(pair).map_err(|_| invalid())?;
+
+            u8::from_str_radix(digits, 16).map_err(|_| invalid())
+        })
+        .collect()
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - i * 6)) & 0x3f;
+                text.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+// Padding is required, whitespace and other characters are errors
+fn decode_base64(text: &[u8]) -> LuaResult<Vec<u8>> {
+    let invalid = || LuaError::RuntimeError("Invalid base64 data.".to_string());
+
+    if !text.len().is_multiple_of(4) {
+        return Err(invalid());
+    }
+
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let groups = text.len() / 4;
+
+    for (i, chunk) in text.chunks(4).enumerate() {
+        // Only the last group can be padded, and only at its end
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+
+        if padding > 2 || (padding > 0 && i + 1 != groups) {
+            return Err(invalid());
+        }
+
+        let mut group = 0u32;
+
+        for c in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET
+                .iter()
+                .position(|letter| letter == c)
+                .ok_or_else(invalid)?;
+
+            group = (group << 6) | value as u32;
+        }
+
+        group <<= 6 * padding;
+
+        data.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+
+    Ok(data)
+}
+
+// Encoders are added to pesto.utils after lume is loaded, like hash
+pub fn register(lua: &Lua, pesto_table: &LuaTable) {
+    let utils_table: LuaTable = pesto_table.get("utils").unwrap();
+
+    let utils_encode_base64 =
+        arguments::function(lua, "pesto.utils.encodeBase64", |_, data: LuaString| {
+            Ok(encode_base64(data.as_bytes()))
+        })
+        .unwrap();
+
+    let utils_decode_base64 =
+        arguments::function(lua, "pesto.utils.decodeBase64", |lua, text: LuaString| {
+            lua.create_string(decode_base64(text.as_bytes())?)
+        })
+        .unwrap();
+
+    let utils_encode_hex =
+        arguments::function(lua, "pesto.utils.encodeHex", |_, data: LuaString| {
+            Ok(encode_hex(data.as_bytes()))
+        })
+        .unwrap();
+
+    let utils_decode_hex =
+        arguments::function(lua, "pesto.utils.decodeHex", |lua, text: LuaString| {
+            lua.create_string(decode_hex(text.as_bytes())?)
+        })
+        .unwrap();
+
+    utils_table
+        .set("encodeBase64", utils_encode_base64)
+        .unwrap();
+    utils_table
+        .set("decodeBase64", utils_decode_base64)
+        .unwrap();
+    utils_table.set("encodeHex", utils_encode_hex).unwrap();
+    utils_table.set("decodeHex", utils_decode_hex).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from RFC 4648 section 10
+    const VECTORS: [(&str, &str, &str); 7] = [
+        ("", "", ""),
+        ("f", "Zg==", "66"),
+        ("fo", "Zm8=", "666f"),
+        ("foo", "Zm9v", "666f6f"),
+        ("foob", "Zm9vYg==", "666f6f62"),
+        ("fooba", "Zm9vYmE=", "666f6f6261"),
+        ("foobar", "Zm9vYmFy", "666f6f626172"),
+    ];
+
+    #[test]
+    fn base64_known_answers() {
+        for (data, base64, _) in VECTORS {
+            assert_eq!(encode_base64(data.as_bytes()), base64);
+            assert_eq!(decode_base64(base64.as_bytes()).unwrap(), data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn hex_known_answers() {
+        for (data, _, hex) in VECTORS {
+            assert_eq!(encode_hex(data.as_bytes()), hex);
+            assert_eq!(decode_hex(hex.as_bytes()).unwrap(), data.as_bytes());
+        }
+
+        assert_eq!(decode_hex(b"666F6F").unwrap(), b"foo");
+    }
+
+    #[test]
+    fn binary_round_trips() {
+        let data: Vec<u8> = (0..=255).rev().collect();
+
+        for length in 0..data.len() {
+            let bytes = &data[..length];
+
+            assert_eq!(
+                decode_base64(encode_base64(bytes).as_bytes()).unwrap(),
+                bytes
+            );
+            assert_eq!(decode_hex(encode_hex(bytes).as_bytes()).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn invalid_base64() {
+        let cases: [&[u8]; 6] = [
+            b"Zm9",
+            b"Zg==Zm9v",
+            b"Z===",
+            b"Zm9v\nYmFy",
+            b"Zm9-",
+            b"Zm 9",
+        ];
+
+        for text in cases {
+            assert!(decode_base64(text).is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_hex() {
+        let cases: [&[u8]; 4] = [b"666", b"6g", b"+f", b"6 "];
+
+        for text in cases {
+            assert!(decode_hex(text).is_err());
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::arguments;
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// Requests made by the game, handled by the main loop between frames
+#[derive(Default)]
+pub struct EventState {
+    pub quit: Option<i32>,
+    pub restart: bool,
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, event_state: &Rc<RefCell<EventState>>) {
+    let event_table = lua.create_table().unwrap();
+
+    let state = event_state.clone();
+    let event_quit = arguments::function(lua, "pesto.event.quit", move |_, code: Option<i32>| {
+        state.borrow_mut().quit = Some(code.unwrap_or(0));
+
+        Ok(())
+    })
+    .unwrap();
+
+    let state = event_state.clone();
+    let event_restart = arguments::function(lua, "pesto.event.restart", move |_, ()| {
+        state.borrow_mut().restart = true;
+
+        Ok(())
+    })
+    .unwrap();
+
+    event_table.set("quit", event_quit).unwrap();
+    event_table.set("restart", event_restart).unwrap();
+
+    pesto_table.set("event", event_table).unwrap();
+}
@@ -0,0 +1,219 @@
+use crate::{
+    arguments,
+    project::{self, Project},
+};
+use mlua::prelude::*;
+use std::{
+    env, fs,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+use walkdir::WalkDir;
+
+// Per game folder for saves, kept out of the project directory so games
+// can't overwrite their own source
+pub fn save_directory(identity: &str) -> PathBuf {
+    let data_directory = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+    };
+
+    data_directory
+        .unwrap_or_else(env::temp_dir)
+        .join("pesto")
+        .join(folder_name(identity))
+}
+
+// The identity or title becomes a single folder name. Separators and
+// characters some systems don't allow in names are replaced, and names
+// that are empty or only dots like '..' fall back to a fixed one
+fn folder_name(identity: &str) -> String {
+    let name: String = identity
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    // Windows drops trailing dots and spaces from names
+    let name = name.trim_end_matches(['.', ' ']).trim_start();
+
+    if name.is_empty() {
+        "game".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// Resolve a path inside the given directory, rejecting anything that could
+// point outside of it
+fn contained_path(directory: &Path, path: &str) -> LuaResult<PathBuf> {
+    let relative = Path::new(path);
+
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid path '{}', paths must be relative and can't contain '..'.",
+            path
+        )));
+    }
+
+    Ok(directory.join(relative))
+}
+
+// Files in the save directory always need a name
+pub fn save_path(save_directory: &Path, path: &str) -> LuaResult<PathBuf> {
+    if path.is_empty() {
+        return Err(LuaError::RuntimeError("Invalid empty path.".to_string()));
+    }
+
+    contained_path(save_directory, path)
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, project: &Rc<Project>, save_directory: &Path) {
+    let filesystem_table = lua.create_table().unwrap();
+
+    // Items are listed relative to the requested directory with forward
+    // slashes, sorted so the order is the same on every platform
+    let items_project = project.clone();
+    let filesystem_get_directory_items = arguments::function(
+        lua,
+        "pesto.filesystem.getDirectoryItems",
+        move |_, (path, recursive, directories): (String, Option<bool>, Option<bool>)| {
+            let root = contained_path(&items_project.directory, &path)?;
+
+            if let Some(archive) = &items_project.archive {
+                return project::archive_items(
+                    archive,
+                    &path,
+                    recursive.unwrap_or(false),
+                    directories.unwrap_or(true),
+                )
+                .ok_or_else(|| LuaError::RuntimeError(format!("Directory '{}' not found.", path)));
+            }
+
+            if !root.is_dir() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Directory '{}' not found.",
+                    path
+                )));
+            }
+
+            let max_depth = if recursive.unwrap_or(false) {
+                usize::MAX
+            } else {
+                1
+            };
+
+            let mut items: Vec<String> = WalkDir::new(&root)
+                .min_depth(1)
+                .max_depth(max_depth)
+                .into_iter()
+                .flatten()
+                .filter(|entry| directories.unwrap_or(true) || !entry.file_type().is_dir())
+                .filter_map(|entry| {
+                    let relative = entry.path().strip_prefix(&root).ok()?;
+
+                    Some(
+                        relative
+                            .components()
+                            .map(|component| component.as_os_str().to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join("/"),
+                    )
+                })
+                .collect();
+
+            items.sort();
+
+            Ok(items)
+        },
+    )
+    .unwrap();
+
+    let directory = save_directory.to_path_buf();
+    let filesystem_read =
+        arguments::function(lua, "pesto.filesystem.read", move |lua, path: String| {
+            let bytes = fs::read(save_path(&directory, &path)?).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not read file '{}': {}", path, err))
+            })?;
+
+            lua.create_string(bytes)
+        })
+        .unwrap();
+
+    let directory = save_directory.to_path_buf();
+    let filesystem_write = arguments::function(
+        lua,
+        "pesto.filesystem.write",
+        move |_, (path, data): (String, LuaString)| {
+            let file_path = save_path(&directory, &path)?;
+
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent).map_err(|err| {
+                    LuaError::RuntimeError(format!("Could not write file '{}': {}", path, err))
+                })?;
+            }
+
+            fs::write(file_path, data.as_bytes()).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not write file '{}': {}", path, err))
+            })
+        },
+    )
+    .unwrap();
+
+    let directory = save_directory.to_path_buf();
+    let filesystem_exists =
+        arguments::function(lua, "pesto.filesystem.exists", move |_, path: String| {
+            Ok(save_path(&directory, &path)?.exists())
+        })
+        .unwrap();
+
+    let directory = save_directory.to_path_buf();
+    let filesystem_remove =
+        arguments::function(lua, "pesto.filesystem.remove", move |_, path: String| {
+            let file_path = save_path(&directory, &path)?;
+
+            let result = if file_path.is_dir() {
+                fs::remove_dir(file_path)
+            } else {
+                fs::remove_file(file_path)
+            };
+
+            result.map_err(|err| {
+                LuaError::RuntimeError(format!("Could not remove '{}': {}", path, err))
+            })
+        })
+        .unwrap();
+
+    let directory = save_directory.to_path_buf();
+    let filesystem_get_save_directory =
+        arguments::function(lua, "pesto.filesystem.getSaveDirectory", move |_, ()| {
+            Ok(directory.to_string_lossy().to_string())
+        })
+        .unwrap();
+
+    filesystem_table
+        .set("getDirectoryItems", filesystem_get_directory_items)
+        .unwrap();
+    filesystem_table.set("read", filesystem_read).unwrap();
+    filesystem_table.set("write", filesystem_write).unwrap();
+    filesystem_table.set("exists", filesystem_exists).unwrap();
+    filesystem_table.set("remove", filesystem_remove).unwrap();
+    filesystem_table
+        .set("getSaveDirectory", filesystem_get_save_directory)
+        .unwrap();
+
+    pesto_table.set("filesystem", filesystem_table).unwrap();
+}
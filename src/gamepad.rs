@@ -0,0 +1,185 @@
+use crate::arguments;
+use mlua::prelude::*;
+use quad_gamepad::{ControllerContext, ControllerStatus, GamepadButton, MAX_DEVICES};
+use std::{cell::RefCell, rc::Rc};
+
+const BUTTONS: &[(&str, GamepadButton)] = &[
+    ("a", GamepadButton::A),
+    ("b", GamepadButton::B),
+    ("x", GamepadButton::X),
+    ("y", GamepadButton::Y),
+    ("dpup", GamepadButton::DpadUp),
+    ("dpdown", GamepadButton::DpadDown),
+    ("dpleft", GamepadButton::DpadLeft),
+    ("dpright", GamepadButton::DpadRight),
+    ("leftshoulder", GamepadButton::BumperLeft),
+    ("rightshoulder", GamepadButton::BumperRight),
+    ("leftstick", GamepadButton::ThumbLeft),
+    ("rightstick", GamepadButton::ThumbRight),
+    ("back", GamepadButton::Back),
+    ("select", GamepadButton::Select),
+    ("start", GamepadButton::Start),
+];
+
+// Axes are reported in the order the backend lists them, which matches
+// these names for common controllers
+const AXES: &[&str] = &[
+    "leftx",
+    "lefty",
+    "rightx",
+    "righty",
+    "triggerleft",
+    "triggerright",
+];
+
+// Linux devices report a single input event per poll
+const POLLS_PER_FRAME: usize = 32;
+
+// Rust side gamepad state, polled every frame by the main loop and kept
+// across reloads
+pub struct GamepadState {
+    context: Option<ControllerContext>,
+}
+
+impl GamepadState {
+    pub fn new() -> Self {
+        // quad-gamepad panics when there is no input directory to scan
+        let available = !cfg!(target_os = "linux") || std::fs::read_dir("/dev/input").is_ok();
+
+        GamepadState {
+            context: if available {
+                ControllerContext::new()
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn update(&mut self) {
+        if let Some(context) = &mut self.context {
+            for _ in 0..POLLS_PER_FRAME {
+                context.update();
+            }
+        }
+    }
+
+    // Disconnected and missing controllers read as idle, so unplugging one
+    // mid game just releases everything
+    fn connected(&self, index: usize) -> bool {
+        self.context.as_ref().is_some_and(|context| {
+            index < MAX_DEVICES && context.state(index).status == ControllerStatus::Connected
+        })
+    }
+}
+
+// Gamepads are numbered from 1 like everywhere else in lua
+fn parse_index(index: i64) -> LuaResult<usize> {
+    if index < 1 {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid gamepad index {}.",
+            index
+        )));
+    }
+
+    Ok(index as usize - 1)
+}
+
+fn parse_button(name: &str) -> LuaResult<GamepadButton> {
+    BUTTONS
+        .iter()
+        .find(|(button_name, _)| *button_name == name)
+        .map(|(_, button)| *button)
+        .ok_or_else(|| LuaError::RuntimeError(format!("Invalid gamepad button '{}'.", name)))
+}
+
+fn parse_axis(name: &str) -> LuaResult<usize> {
+    AXES.iter()
+        .position(|axis_name| *axis_name == name)
+        .ok_or_else(|| LuaError::RuntimeError(format!("Invalid gamepad axis '{}'.", name)))
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, gamepad_state: &Rc<RefCell<GamepadState>>) {
+    let gamepad_table = lua.create_table().unwrap();
+
+    let state = gamepad_state.clone();
+    let gamepad_get_count = arguments::function(lua, "pesto.gamepad.getCount", move |_, ()| {
+        let state = state.borrow();
+
+        Ok((0..MAX_DEVICES)
+            .filter(|index| state.connected(*index))
+            .count())
+    })
+    .unwrap();
+
+    let state = gamepad_state.clone();
+    let gamepad_is_down = arguments::function(
+        lua,
+        "pesto.gamepad.isDown",
+        move |_, (index, button): (i64, String)| {
+            let index = parse_index(index)?;
+            let button = parse_button(&button)?;
+            let state = state.borrow();
+
+            Ok(state.connected(index)
+                && state.context.as_ref().unwrap().state(index).digital_state[button as usize])
+        },
+    )
+    .unwrap();
+
+    let state = gamepad_state.clone();
+    let gamepad_get_axis = arguments::function(
+        lua,
+        "pesto.gamepad.getAxis",
+        move |_, (index, axis): (i64, String)| {
+            let index = parse_index(index)?;
+            let axis = parse_axis(&axis)?;
+            let state = state.borrow();
+
+            if !state.connected(index) {
+                return Ok(0.0);
+            }
+
+            Ok(state.context.as_ref().unwrap().state(index).analog_state[axis])
+        },
+    )
+    .unwrap();
+
+    // quad-gamepad only reads input and has no way to drive the motors, so
+    // every controller reports rumble as unsupported. The arguments are
+    // still checked so games find their mistakes before a backend can rumble
+    let gamepad_set_vibration = arguments::function(
+        lua,
+        "pesto.gamepad.setVibration",
+        |_, (index, left, right, duration): (i64, f32, f32, Option<f32>)| {
+            parse_index(index)?;
+
+            for intensity in [left, right] {
+                if !(0.0..=1.0).contains(&intensity) {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Invalid vibration intensity {}, expected a number from 0 to 1.",
+                        intensity
+                    )));
+                }
+            }
+
+            if duration.is_some_and(|duration| duration.is_nan() || duration < 0.0) {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid vibration duration {}, it can't be negative.",
+                    duration.unwrap()
+                )));
+            }
+
+            Ok(false)
+        },
+    )
+    .unwrap();
+
+    gamepad_table.set("getCount", gamepad_get_count).unwrap();
+    gamepad_table.set("isDown", gamepad_is_down).unwrap();
+    gamepad_table.set("getAxis", gamepad_get_axis).unwrap();
+    gamepad_table
+        .set("setVibration", gamepad_set_vibration)
+        .unwrap();
+
+    pesto_table.set("gamepad", gamepad_table).unwrap();
+}
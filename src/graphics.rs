@@ -0,0 +1,3084 @@
+use crate::{arguments, color, filesystem, project::Project};
+use macroquad::{
+    miniquad::{self, BlendFactor, BlendState, BlendValue, Equation},
+    prelude::*,
+};
+use mlua::{prelude::*, Variadic};
+use regex::Regex;
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::Path,
+    rc::Rc,
+    sync::mpsc,
+    thread,
+};
+
+// Rust side graphics state shared by all drawing functions
+pub struct GraphicsState {
+    pub color: Color,
+    pub line_width: f32,
+    line_style: LineStyle,
+    dash_length: f32,
+    pub point_size: f32,
+    pub font_size: u16,
+    pub font: Option<Font>,
+    pub virtual_resolution: Vec2,
+    pub transform: Mat4,
+    pub transform_stack: Vec<Mat4>,
+    pub camera: Mat4,
+    pub background_color: Color,
+    pub letterbox_color: Color,
+    pub screen_target: Option<RenderTarget>,
+    // Largest render target the GPU allows, unknown in headless runs
+    pub max_texture_size: Option<f32>,
+    pub default_filter: FilterMode,
+    pub pixel_perfect: bool,
+    scissor: Option<(i32, i32, i32, i32)>,
+    // Height of the canvas being drawn to, None for the screen and layers
+    canvas_height: Option<i32>,
+    saved_states: Vec<SavedState>,
+    blend_mode: BlendMode,
+    blend_materials: Vec<(BlendMode, Material)>,
+    stencil: Option<Stencil>,
+    stencil_test: Option<(StencilCompare, u8)>,
+    stencil_stale: bool,
+    layers: Vec<Layer>,
+    layer: Option<i32>,
+    pending_images: Vec<PendingImage>,
+    images_started: usize,
+    stats: Cell<DrawStats>,
+}
+
+impl GraphicsState {
+    pub fn new(virtual_resolution: Vec2) -> Self {
+        GraphicsState {
+            color: WHITE,
+            line_width: 1.0,
+            line_style: LineStyle::Solid,
+            dash_length: 8.0,
+            point_size: 1.0,
+            font_size: 32,
+            font: None,
+            virtual_resolution,
+            transform: Mat4::IDENTITY,
+            transform_stack: Vec::new(),
+            camera: Mat4::IDENTITY,
+            background_color: BLACK,
+            letterbox_color: LIME,
+            screen_target: None,
+            max_texture_size: None,
+            default_filter: FilterMode::Nearest,
+            pixel_perfect: false,
+            scissor: None,
+            canvas_height: None,
+            saved_states: Vec::new(),
+            blend_mode: BlendMode::Alpha,
+            blend_materials: Vec::new(),
+            stencil: None,
+            stencil_test: None,
+            stencil_stale: true,
+            layers: Vec::new(),
+            layer: None,
+            pending_images: Vec::new(),
+            images_started: 0,
+            stats: Cell::new(DrawStats::default()),
+        }
+    }
+
+    // Count a macroquad draw for getStats. Drawing from another texture
+    // than the previous draw is a texture switch
+    fn count_draw(&self, source: DrawSource, vertices: usize) {
+        let mut stats = self.stats.get();
+
+        stats.draw_calls += 1;
+        stats.vertices += vertices;
+
+        if stats.source.is_some_and(|previous| previous != source) {
+            stats.texture_switches += 1;
+        }
+
+        stats.source = Some(source);
+        self.stats.set(stats);
+    }
+}
+
+// What a draw samples from, shapes use macroquad's white texture and text
+// its font atlas
+#[derive(Clone, Copy, PartialEq)]
+enum DrawSource {
+    Shapes,
+    Text,
+    Texture(miniquad::TextureId),
+}
+
+// Draw counters for the current frame, cleared by the main loop
+#[derive(Clone, Copy, Default)]
+struct DrawStats {
+    draw_calls: usize,
+    vertices: usize,
+    texture_switches: usize,
+    source: Option<DrawSource>,
+}
+
+// Called by the main loop at the start of every frame
+pub fn reset_stats(state: &GraphicsState) {
+    state.stats.set(DrawStats::default());
+}
+
+// Drawing settings captured by pesto.graphics.saveState
+struct SavedState {
+    color: Color,
+    line_width: f32,
+    line_style: LineStyle,
+    dash_length: f32,
+    point_size: f32,
+    font: Option<Font>,
+    font_size: u16,
+    blend_mode: BlendMode,
+    scissor: Option<(i32, i32, i32, i32)>,
+}
+
+// Camera drawing into the letterbox target, flipped so the main loop
+// composites it with flip_y
+pub fn letterbox_camera(target: &RenderTarget) -> Camera2D {
+    let size = target.texture.size();
+
+    let mut camera = Camera2D::from_display_rect(Rect::new(0., 0., size.x, size.y));
+    camera.render_target = Some(target.clone());
+
+    camera
+}
+
+// Offscreen target collecting the draws made on one layer during a frame
+struct Layer {
+    index: i32,
+    target: RenderTarget,
+    drawn: bool,
+}
+
+// Get the target of a layer, creating it the first time the layer is used
+// and clearing it the first time it's drawn to in a frame. Clearing discards
+// queued draws, so they're flushed first
+fn layer_target(state: &mut GraphicsState, index: i32) -> LuaResult<RenderTarget> {
+    let size = state
+        .screen_target
+        .as_ref()
+        .ok_or_else(|| LuaError::RuntimeError("No frame to draw into yet.".to_string()))?
+        .texture
+        .size();
+
+    let position = match state
+        .layers
+        .binary_search_by_key(&index, |layer| layer.index)
+    {
+        Ok(position) => position,
+        Err(position) => {
+            let target = render_target(size.x as u32, size.y as u32);
+            target.texture.set_filter(FilterMode::Nearest);
+
+            state.layers.insert(
+                position,
+                Layer {
+                    index,
+                    target,
+                    drawn: false,
+                },
+            );
+
+            position
+        }
+    };
+
+    let layer = &mut state.layers[position];
+
+    if layer.target.texture.size() != size {
+        layer.target = render_target(size.x as u32, size.y as u32);
+        layer.target.texture.set_filter(FilterMode::Nearest);
+        layer.drawn = false;
+    }
+
+    if !layer.drawn {
+        unsafe { get_internal_gl() }.flush();
+
+        set_camera(&letterbox_camera(&layer.target));
+        clear_background(BLANK);
+
+        layer.drawn = true;
+    }
+
+    Ok(layer.target.clone())
+}
+
+// Camera for wherever drawing goes when no canvas is set, the current layer
+// or the letterbox target
+fn drawing_camera(state: &GraphicsState) -> Option<Camera2D> {
+    match state.layer {
+        Some(index) => state
+            .layers
+            .iter()
+            .find(|layer| layer.index == index)
+            .map(|layer| letterbox_camera(&layer.target)),
+        None => state.screen_target.as_ref().map(letterbox_camera),
+    }
+}
+
+// Draw the layers used this frame onto the letterbox target in ascending
+// order, on top of everything drawn without a layer
+fn composite_layers(state: &mut GraphicsState) {
+    state.layer = None;
+
+    let Some(screen) = &state.screen_target else {
+        return;
+    };
+
+    set_camera(&letterbox_camera(screen));
+
+    for layer in state.layers.iter_mut().filter(|layer| layer.drawn) {
+        draw_texture_ex(
+            &layer.target.texture,
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(layer.target.texture.size()),
+                flip_y: true,
+                ..Default::default()
+            },
+        );
+
+        layer.drawn = false;
+    }
+}
+
+// Replace the model matrix applied to subsequent draws with the camera
+// followed by the current transform
+fn apply_transform(state: &GraphicsState) {
+    let gl = unsafe { get_internal_gl() }.quad_gl;
+
+    gl.pop_model_matrix();
+    gl.push_model_matrix(state.camera * state.transform);
+}
+
+// Matrix mapping world coordinates to virtual coordinates for a camera
+// centered on (x, y)
+fn camera_matrix(resolution: Vec2, x: f32, y: f32, zoom: f32, rotation: f32) -> Mat4 {
+    Mat4::from_translation(vec3(resolution.x / 2.0, resolution.y / 2.0, 0.0))
+        * Mat4::from_rotation_z(-rotation)
+        * Mat4::from_scale(vec3(zoom, zoom, 1.0))
+        * Mat4::from_translation(vec3(-x, -y, 0.0))
+}
+
+// The scissor is kept in the coordinates of whatever is being drawn to.
+// Canvases are rendered upright unlike the letterbox target, so their rows
+// are flipped to match
+fn apply_scissor(state: &GraphicsState) {
+    let scissor = state.scissor.map(|(x, y, w, h)| match state.canvas_height {
+        Some(height) => (x, height - (y + h), w, h),
+        None => (x, y, w, h),
+    });
+
+    unsafe { get_internal_gl() }.quad_gl.scissor(scissor);
+}
+
+// Queried from the GL context, so only callable once the window is open
+pub fn max_texture_size() -> f32 {
+    let mut size = 0;
+    unsafe { miniquad::gl::glGetIntegerv(miniquad::gl::GL_MAX_TEXTURE_SIZE, &mut size) };
+
+    size as f32
+}
+
+// Composite the layers, then clear the camera, transform and saved state
+// stacks, scissor, shader, blend mode and stencil, called by the main loop
+// once the game has finished drawing a frame
+pub fn reset_frame(state: &mut GraphicsState) {
+    state.transform = Mat4::IDENTITY;
+    state.transform_stack.clear();
+    state.saved_states.clear();
+    state.camera = Mat4::IDENTITY;
+
+    apply_transform(state);
+
+    state.scissor = None;
+    state.canvas_height = None;
+    apply_scissor(state);
+
+    state.blend_mode = BlendMode::Alpha;
+    state.stencil_test = None;
+    state.stencil_stale = true;
+    gl_use_default_material();
+
+    composite_layers(state);
+}
+
+// Lua handle for a loaded image
+#[derive(Clone)]
+struct Texture(Texture2D);
+
+impl LuaUserData for Texture {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getWidth", |_, this, ()| Ok(this.0.width()));
+        methods.add_method("getHeight", |_, this, ()| Ok(this.0.height()));
+    }
+}
+
+// Progress of an image decoded in the background
+#[derive(Default)]
+enum ImageLoad {
+    #[default]
+    Pending,
+    Loaded(Texture2D),
+    Failed(String),
+}
+
+// Lua handle for an image loaded with newImageAsync
+struct AsyncImage(Rc<RefCell<ImageLoad>>);
+
+impl LuaUserData for AsyncImage {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("isLoaded", |_, this, ()| {
+            Ok(matches!(*this.0.borrow(), ImageLoad::Loaded(_)))
+        });
+
+        // Nil until the image is loaded, a failed load is raised here
+        methods.add_method("getImage", |_, this, ()| match &*this.0.borrow() {
+            ImageLoad::Pending => Ok(None),
+            ImageLoad::Loaded(texture) => Ok(Some(Texture(texture.clone()))),
+            ImageLoad::Failed(message) => Err(LuaError::RuntimeError(message.clone())),
+        });
+    }
+}
+
+// Image being decoded on another thread, textures can only be created on
+// the main thread so it's uploaded once the decoded pixels come back
+struct PendingImage {
+    path: String,
+    filter: FilterMode,
+    receiver: mpsc::Receiver<Result<Image, String>>,
+    handle: Rc<RefCell<ImageLoad>>,
+}
+
+// Upload the images that finished decoding, called by the main loop every
+// frame. Returns the fraction of images loaded while some are still pending
+pub fn poll_images(state: &mut GraphicsState) -> Option<f32> {
+    state.pending_images.retain(|pending| {
+        let result = match pending.receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return true,
+            Err(mpsc::TryRecvError::Disconnected) => Err("decoding stopped".to_string()),
+        };
+
+        *pending.handle.borrow_mut() = match result {
+            Ok(image) => {
+                let texture = Texture2D::from_image(&image);
+                texture.set_filter(pending.filter);
+
+                ImageLoad::Loaded(texture)
+            }
+            Err(err) => ImageLoad::Failed(format!(
+                "Could not decode image '{}': {}",
+                pending.path, err
+            )),
+        };
+
+        false
+    });
+
+    if state.pending_images.is_empty() {
+        state.images_started = 0;
+        return None;
+    }
+
+    Some(1.0 - state.pending_images.len() as f32 / state.images_started as f32)
+}
+
+// Lua handle for a loaded font and the size it's drawn at
+struct FontHandle {
+    font: Font,
+    size: u16,
+}
+
+impl LuaUserData for FontHandle {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getHeight", |_, this, ()| {
+            Ok(font_height(Some(&this.font), this.size))
+        });
+        methods.add_method("getWidth", |_, this, text: String| {
+            Ok(text_size(&text, Some(&this.font), this.size).x)
+        });
+    }
+}
+
+// Lua handle for an offscreen render target
+struct Canvas {
+    target: RenderTarget,
+    camera: Camera2D,
+}
+
+impl LuaUserData for Canvas {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("texture", |_, this| {
+            Ok(Texture(this.target.texture.clone()))
+        });
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getWidth", |_, this, ()| Ok(this.target.texture.width()));
+        methods.add_method("getHeight", |_, this, ()| Ok(this.target.texture.height()));
+    }
+}
+
+// Lua handle for a sub-rectangle of an image of the given reference size
+#[derive(Clone)]
+struct Quad {
+    source: Rect,
+    reference: Vec2,
+}
+
+impl LuaUserData for Quad {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getViewport", |_, this, ()| {
+            Ok((this.source.x, this.source.y, this.source.w, this.source.h))
+        });
+    }
+}
+
+// Quads are defined against their reference size, map them onto the actual
+// texture size
+fn quad_source(quad: &Quad, texture: &Texture2D) -> Rect {
+    let ratio = texture.size() / quad.reference;
+
+    Rect::new(
+        quad.source.x * ratio.x,
+        quad.source.y * ratio.y,
+        quad.source.w * ratio.x,
+        quad.source.h * ratio.y,
+    )
+}
+
+// Lua handle for an image split into nine regions by its border insets, the
+// corners keep their size while the edges and center stretch
+struct NineSlice {
+    texture: Texture2D,
+    left: f32,
+    right: f32,
+    top: f32,
+    bottom: f32,
+}
+
+impl LuaUserData for NineSlice {}
+
+// Scale a pair of borders down to fit in the given size, so corners of a
+// slice drawn smaller than its borders meet in the middle instead of
+// overlapping
+fn fit_borders(first: f32, second: f32, size: f32) -> (f32, f32) {
+    let total = first + second;
+
+    if total > size && total > 0.0 {
+        let ratio = size.max(0.0) / total;
+        (first * ratio, second * ratio)
+    } else {
+        (first, second)
+    }
+}
+
+// Sprites are submitted in chunks that fit in a single macroquad draw call
+const SPRITES_PER_DRAW: usize = 800;
+
+// Arguments of SpriteBatch:add, the position, an optional quad, rotation
+// and scale
+type SpriteArgs<'lua> = (
+    f32,
+    f32,
+    Option<LuaUserDataRef<'lua, Quad>>,
+    Option<f32>,
+    Option<f32>,
+    Option<f32>,
+);
+
+// Lua handle for many sprites of one image, their vertices are computed
+// once when added and submitted together when drawn
+struct SpriteBatch {
+    texture: Texture2D,
+    vertices: Vec<Vertex>,
+    graphics_state: Rc<RefCell<GraphicsState>>,
+}
+
+impl LuaUserData for SpriteBatch {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Sprites keep the color that was active when they were added
+        methods.add_method_mut(
+            "add",
+            |_, this, (x, y, quad, rotation, scale_x, scale_y): SpriteArgs| {
+                let source = quad.map_or(
+                    Rect::new(0.0, 0.0, this.texture.width(), this.texture.height()),
+                    |quad| quad_source(&quad, &this.texture),
+                );
+
+                let scale_x = scale_x.unwrap_or(1.0);
+                let size = source.size() * vec2(scale_x, scale_y.unwrap_or(scale_x));
+
+                let uv = Rect::new(
+                    source.x / this.texture.width(),
+                    source.y / this.texture.height(),
+                    source.w / this.texture.width(),
+                    source.h / this.texture.height(),
+                );
+
+                let rotation = Mat2::from_angle(rotation.unwrap_or(0.0));
+                let color = this.graphics_state.borrow().color;
+
+                for corner in [
+                    vec2(0.0, 0.0),
+                    vec2(1.0, 0.0),
+                    vec2(1.0, 1.0),
+                    vec2(0.0, 1.0),
+                ] {
+                    let position = vec2(x, y) + rotation * (corner * size);
+
+                    this.vertices.push(Vertex::new(
+                        position.x,
+                        position.y,
+                        0.0,
+                        uv.x + corner.x * uv.w,
+                        uv.y + corner.y * uv.h,
+                        color,
+                    ));
+                }
+
+                Ok(this.vertices.len() / 4)
+            },
+        );
+        methods.add_method_mut("clear", |_, this, ()| {
+            this.vertices.clear();
+
+            Ok(())
+        });
+        methods.add_method("getCount", |_, this, ()| Ok(this.vertices.len() / 4));
+        methods.add_method("draw", |_, this, ()| {
+            let indices: Vec<u16> = (0..SPRITES_PER_DRAW as u16)
+                .flat_map(|i| [0, 1, 2, 0, 2, 3].map(|index| i * 4 + index))
+                .collect();
+
+            let gl = unsafe { get_internal_gl() }.quad_gl;
+
+            gl.texture(Some(&this.texture));
+            gl.draw_mode(DrawMode::Triangles);
+
+            let state = this.graphics_state.borrow();
+            let source = DrawSource::Texture(this.texture.raw_miniquad_id());
+
+            for vertices in this.vertices.chunks(SPRITES_PER_DRAW * 4) {
+                gl.geometry(vertices, &indices[..vertices.len() / 4 * 6]);
+                state.count_draw(source, vertices.len());
+            }
+
+            gl.texture(None);
+
+            Ok(())
+        });
+    }
+}
+
+// Lua handle for a spritesheet animation, frames are sliced left to right
+// then top to bottom and each one has its own duration
+struct Animation {
+    texture: Texture2D,
+    frames: Vec<Rect>,
+    durations: Vec<f32>,
+    looping: bool,
+    frame: usize,
+    elapsed: f32,
+    graphics_state: Rc<RefCell<GraphicsState>>,
+}
+
+impl Animation {
+    // Past the last frame a looping animation starts over, otherwise it
+    // stays on the last frame
+    fn update(&mut self, dt: f32) -> LuaResult<()> {
+        if !(dt.is_finite() && dt >= 0.0) {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid dt {}, expected a positive number or zero.",
+                dt
+            )));
+        }
+
+        if self.looping {
+            // Whole loops are wrapped away first, so big steps and tiny
+            // durations only ever walk through the frames once. Summed as
+            // f64 so the total can't overflow or swallow small durations
+            let total: f64 = self.durations.iter().map(|duration| *duration as f64).sum();
+            let start: f64 = self.durations[..self.frame]
+                .iter()
+                .map(|duration| *duration as f64)
+                .sum();
+
+            let mut position = (start + self.elapsed as f64 + dt as f64) % total;
+
+            self.frame = 0;
+
+            while self.frame + 1 < self.frames.len()
+                && position >= self.durations[self.frame] as f64
+            {
+                position -= self.durations[self.frame] as f64;
+                self.frame += 1;
+            }
+
+            self.elapsed = position as f32;
+        } else {
+            self.elapsed += dt;
+
+            while self.elapsed >= self.durations[self.frame] {
+                if self.frame + 1 == self.frames.len() {
+                    self.elapsed = self.durations[self.frame];
+                    break;
+                }
+
+                self.elapsed -= self.durations[self.frame];
+                self.frame += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finished(&self) -> bool {
+        !self.looping
+            && self.frame + 1 == self.frames.len()
+            && self.elapsed >= self.durations[self.frame]
+    }
+}
+
+impl LuaUserData for Animation {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("update", |_, this, dt: f32| {
+            this.update(dt)?;
+
+            Ok(this.frame + 1)
+        });
+        methods.add_method(
+            "draw",
+            |_,
+             this,
+             (x, y, rotation, scale_x, scale_y): (
+                f32,
+                f32,
+                Option<f32>,
+                Option<f32>,
+                Option<f32>,
+            )| {
+                let scale_x = scale_x.unwrap_or(1.0);
+
+                draw_image(
+                    &this.texture,
+                    Some(this.frames[this.frame]),
+                    x,
+                    y,
+                    rotation.unwrap_or(0.0),
+                    vec2(scale_x, scale_y.unwrap_or(scale_x)),
+                    &this.graphics_state.borrow(),
+                );
+
+                Ok(this.frame + 1)
+            },
+        );
+        methods.add_method("getFrame", |_, this, ()| Ok(this.frame + 1));
+        methods.add_method_mut("setFrame", |_, this, frame: usize| {
+            if frame < 1 || frame > this.frames.len() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid frame {}, the animation has {} frames.",
+                    frame,
+                    this.frames.len()
+                )));
+            }
+
+            this.frame = frame - 1;
+            this.elapsed = 0.0;
+
+            Ok(())
+        });
+        methods.add_method("getFrameCount", |_, this, ()| Ok(this.frames.len()));
+        methods.add_method("isFinished", |_, this, ()| Ok(this.finished()));
+        methods.add_method_mut("reset", |_, this, ()| {
+            this.frame = 0;
+            this.elapsed = 0.0;
+
+            Ok(())
+        });
+    }
+}
+
+// Draw a texture or part of it with its top left corner at (x, y), rotating
+// around that corner
+fn draw_image(
+    texture: &Texture2D,
+    source: Option<Rect>,
+    x: f32,
+    y: f32,
+    rotation: f32,
+    scale: Vec2,
+    state: &GraphicsState,
+) {
+    let size = source.map_or(texture.size(), |source| source.size());
+
+    state.count_draw(DrawSource::Texture(texture.raw_miniquad_id()), 4);
+
+    draw_texture_ex(
+        texture,
+        x,
+        y,
+        state.color,
+        DrawTextureParams {
+            dest_size: Some(size * scale),
+            source,
+            rotation,
+            pivot: Some(vec2(x, y)),
+            ..Default::default()
+        },
+    );
+}
+
+// macroquad's default shader, used to build a material for every blend mode
+// other than alpha
+const DEFAULT_VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}"#;
+
+const DEFAULT_FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = color * texture2D(Texture, uv);
+}"#;
+
+// How new pixels are combined with the ones already drawn. Every mode maps
+// to a single blend equation, which all of macroquad's backends support
+#[derive(Clone, Copy, PartialEq)]
+enum BlendMode {
+    Alpha,
+    Add,
+    Multiply,
+    Screen,
+}
+
+fn parse_filter(filter: &str) -> LuaResult<FilterMode> {
+    match filter {
+        "nearest" => Ok(FilterMode::Nearest),
+        "linear" => Ok(FilterMode::Linear),
+        _ => Err(LuaError::RuntimeError(format!(
+            "Invalid filter '{}', expected 'nearest' or 'linear'.",
+            filter
+        ))),
+    }
+}
+
+fn parse_blend_mode(mode: &str) -> LuaResult<BlendMode> {
+    match mode {
+        "alpha" => Ok(BlendMode::Alpha),
+        "add" => Ok(BlendMode::Add),
+        "multiply" => Ok(BlendMode::Multiply),
+        "screen" => Ok(BlendMode::Screen),
+        _ => Err(LuaError::RuntimeError(format!(
+            "Invalid blend mode '{}', expected 'alpha', 'add', 'multiply' or 'screen'.",
+            mode
+        ))),
+    }
+}
+
+fn blend_state(mode: BlendMode) -> BlendState {
+    let (source, destination) = match mode {
+        BlendMode::Alpha => (
+            BlendFactor::Value(BlendValue::SourceAlpha),
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+        ),
+        BlendMode::Add => (
+            BlendFactor::Value(BlendValue::SourceAlpha),
+            BlendFactor::One,
+        ),
+        BlendMode::Multiply => (
+            BlendFactor::Value(BlendValue::DestinationColor),
+            BlendFactor::Zero,
+        ),
+        BlendMode::Screen => (
+            BlendFactor::One,
+            BlendFactor::OneMinusValue(BlendValue::SourceColor),
+        ),
+    };
+
+    BlendState::new(Equation::Add, source, destination)
+}
+
+// Switch to the material drawing with the current blend mode, compiling it
+// the first time the mode is used
+fn apply_blend_mode(state: &mut GraphicsState) {
+    let mode = state.blend_mode;
+
+    if mode == BlendMode::Alpha {
+        gl_use_default_material();
+        return;
+    }
+
+    if !state
+        .blend_materials
+        .iter()
+        .any(|(other, _)| *other == mode)
+    {
+        let material = load_material(
+            ShaderSource::Glsl {
+                vertex: DEFAULT_VERTEX,
+                fragment: DEFAULT_FRAGMENT,
+            },
+            MaterialParams {
+                pipeline_params: PipelineParams {
+                    color_blend: Some(blend_state(mode)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        state.blend_materials.push((mode, material));
+    }
+
+    let (_, material) = state
+        .blend_materials
+        .iter()
+        .find(|(other, _)| *other == mode)
+        .unwrap();
+
+    gl_use_material(material);
+}
+
+// Writes the stencil value into the alpha channel of the mask wherever the
+// drawn texture isn't fully transparent
+const STENCIL_WRITE_FRAGMENT: &str = r#"#version 100
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+uniform mediump float Value;
+
+void main() {
+    if (texture2D(Texture, uv).a == 0.0) {
+        discard;
+    }
+
+    gl_FragColor = vec4(Value);
+}"#;
+
+// Draws like the default shader, but only where the mask value under the
+// pixel passes the comparison against Value
+const STENCIL_TEST_FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+uniform sampler2D Mask;
+uniform mediump vec2 MaskSize;
+uniform mediump float Value;
+uniform mediump float Compare;
+
+void main() {
+    mediump float stencil = floor(texture2D(Mask, gl_FragCoord.xy / MaskSize).a * 255.0 + 0.5);
+    bool pass;
+
+    if (Compare < 0.5) {
+        pass = stencil == Value;
+    } else if (Compare < 1.5) {
+        pass = stencil != Value;
+    } else if (Compare < 2.5) {
+        pass = stencil < Value;
+    } else if (Compare < 3.5) {
+        pass = stencil <= Value;
+    } else if (Compare < 4.5) {
+        pass = stencil > Value;
+    } else {
+        pass = stencil >= Value;
+    }
+
+    if (!pass) {
+        discard;
+    }
+
+    gl_FragColor = color * texture2D(Texture, uv);
+}"#;
+
+// How drawing inside pesto.graphics.stencil changes the mask
+#[derive(Clone, Copy, PartialEq)]
+enum StencilAction {
+    Replace,
+    Increment,
+    Decrement,
+}
+
+// Comparisons between the mask and the test value, numbered as the test
+// shader expects them
+#[derive(Clone, Copy, PartialEq)]
+enum StencilCompare {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+fn parse_stencil_action(action: &str) -> LuaResult<StencilAction> {
+    match action {
+        "replace" => Ok(StencilAction::Replace),
+        "increment" => Ok(StencilAction::Increment),
+        "decrement" => Ok(StencilAction::Decrement),
+        _ => Err(LuaError::RuntimeError(format!(
+            "Invalid stencil action '{}', expected 'replace', 'increment' or 'decrement'.",
+            action
+        ))),
+    }
+}
+
+fn parse_stencil_compare(compare: &str) -> LuaResult<StencilCompare> {
+    match compare {
+        "equal" => Ok(StencilCompare::Equal),
+        "notequal" => Ok(StencilCompare::NotEqual),
+        "less" => Ok(StencilCompare::Less),
+        "lequal" => Ok(StencilCompare::LessEqual),
+        "greater" => Ok(StencilCompare::Greater),
+        "gequal" => Ok(StencilCompare::GreaterEqual),
+        _ => Err(LuaError::RuntimeError(format!(
+            "Invalid stencil comparison '{}', expected 'equal', 'notequal', 'less', 'lequal', 'greater' or 'gequal'.",
+            compare
+        ))),
+    }
+}
+
+fn stencil_value(value: i64) -> LuaResult<u8> {
+    u8::try_from(value).map_err(|_| {
+        LuaError::RuntimeError(format!(
+            "Invalid stencil value {}, expected 0 to 255.",
+            value
+        ))
+    })
+}
+
+// macroquad's render targets have no stencil attachment, so the stencil
+// buffer is emulated with a mask target the size of the letterbox target.
+// This only needs render targets and GLSL 100, which every backend pesto
+// supports, so there's nothing to fall back from
+struct Stencil {
+    target: RenderTarget,
+    write_materials: Vec<(StencilAction, Material)>,
+    test_material: Material,
+}
+
+impl Stencil {
+    fn new(size: Vec2) -> Self {
+        let write_material = |blend| {
+            load_material(
+                ShaderSource::Glsl {
+                    vertex: DEFAULT_VERTEX,
+                    fragment: STENCIL_WRITE_FRAGMENT,
+                },
+                MaterialParams {
+                    pipeline_params: PipelineParams {
+                        color_blend: blend,
+                        ..Default::default()
+                    },
+                    uniforms: vec![("Value".to_string(), UniformType::Float1)],
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        // Adding and subtracting saturate at 0 and 255 in an 8 bit target,
+        // which matches a stencil buffer's increment and decrement
+        let write_materials = vec![
+            (StencilAction::Replace, write_material(None)),
+            (
+                StencilAction::Increment,
+                write_material(Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::One,
+                    BlendFactor::One,
+                ))),
+            ),
+            (
+                StencilAction::Decrement,
+                write_material(Some(BlendState::new(
+                    Equation::ReverseSubtract,
+                    BlendFactor::One,
+                    BlendFactor::One,
+                ))),
+            ),
+        ];
+
+        let test_material = load_material(
+            ShaderSource::Glsl {
+                vertex: DEFAULT_VERTEX,
+                fragment: STENCIL_TEST_FRAGMENT,
+            },
+            MaterialParams {
+                pipeline_params: PipelineParams {
+                    color_blend: Some(blend_state(BlendMode::Alpha)),
+                    ..Default::default()
+                },
+                uniforms: vec![
+                    ("MaskSize".to_string(), UniformType::Float2),
+                    ("Value".to_string(), UniformType::Float1),
+                    ("Compare".to_string(), UniformType::Float1),
+                ],
+                textures: vec!["Mask".to_string()],
+            },
+        )
+        .unwrap();
+
+        let mut stencil = Stencil {
+            target: render_target(1, 1),
+            write_materials,
+            test_material,
+        };
+
+        stencil.resize(size);
+        stencil
+    }
+
+    fn resize(&mut self, size: Vec2) {
+        self.target = render_target(size.x as u32, size.y as u32);
+        self.target.texture.set_filter(FilterMode::Nearest);
+
+        self.test_material
+            .set_texture("Mask", self.target.texture.clone());
+        self.test_material.set_uniform("MaskSize", size);
+    }
+}
+
+// Get the mask for the current letterbox target, clearing it when it's left
+// over from a previous frame or the caller asks for a fresh one. Clearing
+// discards queued draws, so they're flushed first
+fn stencil_mask(state: &mut GraphicsState, clear: bool) -> LuaResult<&mut Stencil> {
+    let size = state
+        .screen_target
+        .as_ref()
+        .ok_or_else(|| LuaError::RuntimeError("No frame to draw into yet.".to_string()))?
+        .texture
+        .size();
+
+    let stencil = state.stencil.get_or_insert_with(|| Stencil::new(size));
+
+    if stencil.target.texture.size() != size {
+        stencil.resize(size);
+        state.stencil_stale = true;
+    }
+
+    if clear || state.stencil_stale {
+        unsafe { get_internal_gl() }.flush();
+
+        set_camera(&letterbox_camera(&stencil.target));
+        clear_background(BLANK);
+
+        if let Some(camera) = drawing_camera(state) {
+            set_camera(&camera);
+        }
+
+        state.stencil_stale = false;
+    }
+
+    Ok(state.stencil.as_mut().unwrap())
+}
+
+// Switch to the material for the current blend mode, or the stencil test's
+// when one is set
+fn apply_material(state: &mut GraphicsState) {
+    match (state.stencil_test, &state.stencil) {
+        (Some((compare, value)), Some(stencil)) => {
+            stencil.test_material.set_uniform("Value", value as f32);
+            stencil
+                .test_material
+                .set_uniform("Compare", compare as u8 as f32);
+
+            gl_use_material(&stencil.test_material);
+        }
+        _ => apply_blend_mode(state),
+    }
+}
+
+// Lua handle for a compiled shader and the uniforms it declares
+#[derive(Clone)]
+struct Shader {
+    material: Material,
+    uniforms: Vec<(String, UniformType)>,
+}
+
+impl LuaUserData for Shader {}
+
+// Materials only accept uniforms declared when they are created, so the
+// float and vector uniforms are collected straight from the sources
+fn parse_uniforms(sources: &[&str]) -> Vec<(String, UniformType)> {
+    let regex = Regex::new(r"uniform\s+(?:\w+\s+)?(float|vec2|vec3|vec4)\s+(\w+)\s*;").unwrap();
+    let mut uniforms: Vec<(String, UniformType)> = Vec::new();
+
+    for source in sources {
+        for captures in regex.captures_iter(source) {
+            let name = captures[2].to_string();
+
+            // Declared by macroquad itself
+            if name == "_Time" || uniforms.iter().any(|(other, _)| *other == name) {
+                continue;
+            }
+
+            let kind = match &captures[1] {
+                "float" => UniformType::Float1,
+                "vec2" => UniformType::Float2,
+                "vec3" => UniformType::Float3,
+                _ => UniformType::Float4,
+            };
+
+            uniforms.push((name, kind));
+        }
+    }
+
+    uniforms
+}
+
+// How shapes are drawn
+#[derive(Clone, Copy, PartialEq)]
+enum FillMode {
+    Fill,
+    Line,
+}
+
+fn parse_fill_mode(mode: &str) -> LuaResult<FillMode> {
+    match mode {
+        "fill" => Ok(FillMode::Fill),
+        "line" => Ok(FillMode::Line),
+        _ => Err(LuaError::RuntimeError(format!(
+            "Invalid draw mode '{}', expected 'fill' or 'line'.",
+            mode
+        ))),
+    }
+}
+
+// How outlines and lines are stroked
+#[derive(Clone, Copy, PartialEq)]
+enum LineStyle {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+fn parse_line_style(style: &str) -> LuaResult<LineStyle> {
+    match style {
+        "solid" => Ok(LineStyle::Solid),
+        "dashed" => Ok(LineStyle::Dashed),
+        "dotted" => Ok(LineStyle::Dotted),
+        _ => Err(LuaError::RuntimeError(format!(
+            "Invalid line style '{}', expected 'solid', 'dashed' or 'dotted'.",
+            style
+        ))),
+    }
+}
+
+// Draw a connected run of lines in the current line style. The pattern
+// carries on across corners instead of restarting at every point, dashes
+// and the gaps between them are the dash length while dots are as long as
+// the line is wide and the dash length apart
+fn draw_stroke(points: &[Vec2], closed: bool, state: &GraphicsState) {
+    let closing = match (closed, points.first(), points.last()) {
+        (true, Some(first), Some(last)) if points.len() > 2 => Some([*last, *first]),
+        _ => None,
+    };
+    let segments = points.windows(2).chain(closing.as_ref().map(|s| &s[..]));
+
+    let (on, off) = match state.line_style {
+        LineStyle::Solid => {
+            for segment in segments {
+                state.count_draw(DrawSource::Shapes, 4);
+                draw_line(
+                    segment[0].x,
+                    segment[0].y,
+                    segment[1].x,
+                    segment[1].y,
+                    state.line_width,
+                    state.color,
+                );
+            }
+
+            return;
+        }
+        LineStyle::Dashed => (state.dash_length, state.dash_length),
+        LineStyle::Dotted => (state.line_width.max(1.0), state.dash_length),
+    };
+
+    let period = on + off;
+    let mut travelled = 0.0;
+
+    for segment in segments {
+        let (start, end) = (segment[0], segment[1]);
+        let length = start.distance(end);
+
+        if length == 0.0 {
+            continue;
+        }
+
+        let direction = (end - start) / length;
+        let mut position = 0.0;
+
+        while position < length {
+            let phase = travelled % period;
+
+            let next = if phase < on {
+                let dash_end = (position + on - phase).min(length);
+                let (from, to) = (start + direction * position, start + direction * dash_end);
+
+                state.count_draw(DrawSource::Shapes, 4);
+                draw_line(from.x, from.y, to.x, to.y, state.line_width, state.color);
+
+                dash_end
+            } else {
+                (position + period - phase).min(length)
+            };
+
+            travelled += next - position;
+            position = next;
+        }
+    }
+}
+
+// Pair up a flat list of coordinates into points
+fn parse_points(coords: &[f32], min_points: usize) -> LuaResult<Vec<Vec2>> {
+    if coords.len() < min_points * 2 || !coords.len().is_multiple_of(2) {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid number of coordinates ({}), expected an even number of at least {}.",
+            coords.len(),
+            min_points * 2
+        )));
+    }
+
+    Ok(coords
+        .chunks(2)
+        .map(|point| vec2(point[0], point[1]))
+        .collect())
+}
+
+// Sample points along an elliptical arc, using more segments for bigger
+// and longer arcs so small ones aren't over-tessellated
+fn arc_points(center: Vec2, radius: Vec2, angle1: f32, angle2: f32) -> Vec<Vec2> {
+    let span = (angle2 - angle1).abs().min(std::f32::consts::TAU);
+    let full_segments = (radius.max_element().sqrt() * 8.0).clamp(8.0, 256.0);
+    let segments = ((full_segments * span / std::f32::consts::TAU).ceil() as usize).max(1);
+
+    (0..=segments)
+        .map(|i| {
+            let angle = angle1 + (angle2 - angle1) * i as f32 / segments as f32;
+
+            center + vec2(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+// Draw sampled points as a fan around the center or as an outline
+fn draw_arc_points(mode: FillMode, center: Vec2, points: &[Vec2], state: &GraphicsState) {
+    match mode {
+        FillMode::Fill => {
+            for segment in points.windows(2) {
+                state.count_draw(DrawSource::Shapes, 3);
+                draw_triangle(center, segment[0], segment[1], state.color);
+            }
+        }
+        FillMode::Line => draw_stroke(points, false, state),
+    }
+}
+
+// Every visible character is a quad of the font atlas
+fn glyph_vertices(line: &str) -> usize {
+    line.chars().filter(|c| !c.is_whitespace()).count() * 4
+}
+
+// Height of the glyphs from the top of the capitals to the bottom of the
+// descenders, which is less than the line spacing print uses
+fn font_height(font: Option<&Font>, font_size: u16) -> f32 {
+    measure_text("Mgjpqy", font, font_size, 1.0).height
+}
+
+// Size of text drawn with print, the width of the longest line by the
+// height of all lines
+fn text_size(text: &str, font: Option<&Font>, font_size: u16) -> Vec2 {
+    let width = text
+        .lines()
+        .map(|line| measure_text(line, font, font_size, 1.0).width)
+        .fold(0.0, f32::max);
+
+    vec2(width, text.lines().count() as f32 * font_size as f32)
+}
+
+// Split text into lines no wider than limit pixels, breaking on whitespace
+pub fn wrap_text(text: &str, limit: f32, font: Option<&Font>, font_size: u16) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+
+            if !line.is_empty() && measure_text(&candidate, font, font_size, 1.0).width > limit {
+                lines.push(line);
+                line = word.to_string();
+            } else {
+                line = candidate;
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+pub fn register(
+    lua: &Lua,
+    pesto_table: &LuaTable,
+    project: &Rc<Project>,
+    save_directory: &Path,
+    graphics_state: &Rc<RefCell<GraphicsState>>,
+) {
+    let graphics_table = lua.create_table().unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_color = arguments::function(
+        lua,
+        "pesto.graphics.setColor",
+        move |lua, args: LuaMultiValue| {
+            state.borrow_mut().color = color::parse_color(lua, args)?;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Both return a color table for setColor, the hue is in degrees
+    let graphics_hsv = arguments::function(
+        lua,
+        "pesto.graphics.hsv",
+        |lua, (h, s, v, a): (f32, f32, f32, Option<f32>)| {
+            let (r, g, b) = color::hsv(h, s, v);
+
+            color::new_color(lua, Color::new(r, g, b, a.unwrap_or(1.0)))
+        },
+    )
+    .unwrap();
+
+    let graphics_hsl = arguments::function(
+        lua,
+        "pesto.graphics.hsl",
+        |lua, (h, s, l, a): (f32, f32, f32, Option<f32>)| {
+            let (r, g, b) = color::hsl(h, s, l);
+
+            color::new_color(lua, Color::new(r, g, b, a.unwrap_or(1.0)))
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_color = arguments::function(lua, "pesto.graphics.getColor", move |_, ()| {
+        let color = state.borrow().color;
+
+        Ok((color.r, color.g, color.b, color.a))
+    })
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_background_color = arguments::function(
+        lua,
+        "pesto.graphics.setBackgroundColor",
+        move |lua, args: LuaMultiValue| {
+            state.borrow_mut().background_color = color::parse_color(lua, args)?;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_letterbox_color = arguments::function(
+        lua,
+        "pesto.graphics.setLetterboxColor",
+        move |lua, args: LuaMultiValue| {
+            // The letterbox is always opaque
+            state.borrow_mut().letterbox_color = Color {
+                a: 1.0,
+                ..color::parse_color(lua, args)?
+            };
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_circle = arguments::function(
+        lua,
+        "pesto.graphics.circle",
+        move |_, (x, y, radius): (f32, f32, f32)| {
+            if !x.is_finite() || !y.is_finite() || !radius.is_finite() || radius < 0.0 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid circle at ({}, {}) with radius {}.",
+                    x, y, radius
+                )));
+            }
+
+            let state = state.borrow();
+
+            // macroquad circles are 20 sided polygons
+            state.count_draw(DrawSource::Shapes, 22);
+            draw_circle(x, y, radius, state.color);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_rectangle = arguments::function(
+        lua,
+        "pesto.graphics.rectangle",
+        move |_, (mode, x, y, w, h): (String, f32, f32, f32, f32)| {
+            let state = state.borrow();
+
+            match parse_fill_mode(&mode)? {
+                FillMode::Fill => {
+                    state.count_draw(DrawSource::Shapes, 4);
+                    draw_rectangle(x, y, w, h, state.color);
+                }
+                FillMode::Line if state.line_style == LineStyle::Solid => {
+                    state.count_draw(DrawSource::Shapes, 8);
+                    draw_rectangle_lines(x, y, w, h, state.line_width, state.color);
+                }
+                // Inset by half the width like solid outlines, so the
+                // stroke stays inside the rectangle
+                FillMode::Line => {
+                    let inset = state.line_width * 0.5;
+                    let (left, top) = (x + inset, y + inset);
+                    let (right, bottom) = (x + w - inset, y + h - inset);
+
+                    draw_stroke(
+                        &[
+                            vec2(left, top),
+                            vec2(right, top),
+                            vec2(right, bottom),
+                            vec2(left, bottom),
+                        ],
+                        true,
+                        &state,
+                    );
+                }
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // A single quad with a color at each corner, the GPU interpolates
+    // between them
+    let state = graphics_state.clone();
+    let graphics_rectangle_gradient = arguments::function(
+        lua,
+        "pesto.graphics.rectangleGradient",
+        move |lua,
+              (x, y, w, h, first, second, direction): (
+            f32,
+            f32,
+            f32,
+            f32,
+            LuaTable,
+            LuaTable,
+            Option<String>,
+        )| {
+            let first =
+                color::parse_color(lua, LuaMultiValue::from_vec(vec![first.into_lua(lua)?]))?;
+            let second =
+                color::parse_color(lua, LuaMultiValue::from_vec(vec![second.into_lua(lua)?]))?;
+
+            // Corners go clockwise from the top left
+            let colors = match direction.as_deref().unwrap_or("vertical") {
+                "vertical" => [first, first, second, second],
+                "horizontal" => [first, second, second, first],
+                direction => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Invalid gradient direction '{}', expected 'vertical' or 'horizontal'.",
+                        direction
+                    )))
+                }
+            };
+
+            let corners = [
+                vec2(x, y),
+                vec2(x + w, y),
+                vec2(x + w, y + h),
+                vec2(x, y + h),
+            ];
+            let vertices: Vec<Vertex> = corners
+                .iter()
+                .zip(colors)
+                .map(|(corner, color)| Vertex::new(corner.x, corner.y, 0.0, 0.0, 0.0, color))
+                .collect();
+
+            let gl = unsafe { get_internal_gl() }.quad_gl;
+
+            gl.texture(None);
+            gl.draw_mode(DrawMode::Triangles);
+            gl.geometry(&vertices, &[0, 1, 2, 0, 2, 3]);
+
+            state.borrow().count_draw(DrawSource::Shapes, 4);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Insets are in pixels of the image and have to leave room for the
+    // center
+    let graphics_new_nine_slice = arguments::function(
+        lua,
+        "pesto.graphics.newNineSlice",
+        |_, (image, left, right, top, bottom): (LuaUserDataRef<Texture>, f32, f32, f32, f32)| {
+            let size = image.0.size();
+
+            if [left, right, top, bottom]
+                .iter()
+                .any(|inset| inset.is_nan() || *inset < 0.0)
+                || left + right > size.x
+                || top + bottom > size.y
+            {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid nine slice insets, they can't be negative and must fit in the {}x{} image.",
+                    size.x, size.y
+                )));
+            }
+
+            Ok(NineSlice {
+                texture: image.0.clone(),
+                left,
+                right,
+                top,
+                bottom,
+            })
+        },
+    )
+    .unwrap();
+
+    // Each region maps a cell of the source grid onto the matching cell of
+    // the destination grid, empty ones are skipped
+    let state = graphics_state.clone();
+    let graphics_draw_nine_slice = arguments::function(
+        lua,
+        "pesto.graphics.drawNineSlice",
+        move |_, (slice, x, y, w, h): (LuaUserDataRef<NineSlice>, f32, f32, f32, f32)| {
+            let state = state.borrow();
+            let size = slice.texture.size();
+
+            let (left, right) = fit_borders(slice.left, slice.right, w);
+            let (top, bottom) = fit_borders(slice.top, slice.bottom, h);
+
+            let source_columns = [0.0, slice.left, size.x - slice.right, size.x];
+            let source_rows = [0.0, slice.top, size.y - slice.bottom, size.y];
+            let columns = [x, x + left, x + w - right, x + w];
+            let rows = [y, y + top, y + h - bottom, y + h];
+
+            for row in 0..3 {
+                for column in 0..3 {
+                    let source = Rect::new(
+                        source_columns[column],
+                        source_rows[row],
+                        source_columns[column + 1] - source_columns[column],
+                        source_rows[row + 1] - source_rows[row],
+                    );
+                    let dest_size = vec2(
+                        columns[column + 1] - columns[column],
+                        rows[row + 1] - rows[row],
+                    );
+
+                    if source.w <= 0.0
+                        || source.h <= 0.0
+                        || dest_size.x <= 0.0
+                        || dest_size.y <= 0.0
+                    {
+                        continue;
+                    }
+
+                    state.count_draw(DrawSource::Texture(slice.texture.raw_miniquad_id()), 4);
+                    draw_texture_ex(
+                        &slice.texture,
+                        columns[column],
+                        rows[row],
+                        state.color,
+                        DrawTextureParams {
+                            dest_size: Some(dest_size),
+                            source: Some(source),
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_triangle = arguments::function(
+        lua,
+        "pesto.graphics.triangle",
+        move |_, (mode, x1, y1, x2, y2, x3, y3): (String, f32, f32, f32, f32, f32, f32)| {
+            let state = state.borrow();
+
+            let (v1, v2, v3) = (vec2(x1, y1), vec2(x2, y2), vec2(x3, y3));
+
+            match parse_fill_mode(&mode)? {
+                FillMode::Fill => {
+                    state.count_draw(DrawSource::Shapes, 3);
+                    draw_triangle(v1, v2, v3, state.color);
+                }
+                FillMode::Line => draw_stroke(&[v1, v2, v3], true, &state),
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Polygons are filled as a triangle fan, so they have to be convex
+    let state = graphics_state.clone();
+    let graphics_polygon = arguments::function(
+        lua,
+        "pesto.graphics.polygon",
+        move |_, (mode, coords): (String, Variadic<f32>)| {
+            let mode = parse_fill_mode(&mode)?;
+            let points = parse_points(&coords, 3)?;
+
+            let state = state.borrow();
+
+            match mode {
+                FillMode::Fill => {
+                    for i in 1..points.len() - 1 {
+                        state.count_draw(DrawSource::Shapes, 3);
+                        draw_triangle(points[0], points[i], points[i + 1], state.color);
+                    }
+                }
+                FillMode::Line => draw_stroke(&points, true, &state),
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_ellipse = arguments::function(
+        lua,
+        "pesto.graphics.ellipse",
+        move |_, (mode, x, y, radius_x, radius_y): (String, f32, f32, f32, f32)| {
+            let mode = parse_fill_mode(&mode)?;
+
+            let center = vec2(x, y);
+            let points = arc_points(center, vec2(radius_x, radius_y), 0.0, std::f32::consts::TAU);
+
+            draw_arc_points(mode, center, &points, &state.borrow());
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_arc = arguments::function(
+        lua,
+        "pesto.graphics.arc",
+        move |_, (mode, x, y, radius, angle1, angle2): (String, f32, f32, f32, f32, f32)| {
+            let mode = parse_fill_mode(&mode)?;
+
+            let center = vec2(x, y);
+            let points = arc_points(center, Vec2::splat(radius), angle1, angle2);
+
+            draw_arc_points(mode, center, &points, &state.borrow());
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_line_width =
+        arguments::function(lua, "pesto.graphics.setLineWidth", move |_, width: f32| {
+            if !width.is_finite() || width <= 0.0 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid line width {}, expected a positive number.",
+                    width
+                )));
+            }
+
+            state.borrow_mut().line_width = width;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_line_width =
+        arguments::function(lua, "pesto.graphics.getLineWidth", move |_, ()| {
+            Ok(state.borrow().line_width)
+        })
+        .unwrap();
+
+    // The dash length is kept when it's left out
+    let state = graphics_state.clone();
+    let graphics_set_line_style = arguments::function(
+        lua,
+        "pesto.graphics.setLineStyle",
+        move |_, (style, dash_length): (String, Option<f32>)| {
+            let style = parse_line_style(&style)?;
+            let mut state = state.borrow_mut();
+
+            if let Some(dash_length) = dash_length {
+                if !dash_length.is_finite() || dash_length <= 0.0 {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Invalid dash length {}, expected a positive number.",
+                        dash_length
+                    )));
+                }
+
+                state.dash_length = dash_length;
+            }
+
+            state.line_style = style;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_line_style =
+        arguments::function(lua, "pesto.graphics.getLineStyle", move |_, ()| {
+            let state = state.borrow();
+
+            let style = match state.line_style {
+                LineStyle::Solid => "solid",
+                LineStyle::Dashed => "dashed",
+                LineStyle::Dotted => "dotted",
+            };
+
+            Ok((style, state.dash_length))
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_point_size =
+        arguments::function(lua, "pesto.graphics.setPointSize", move |_, size: f32| {
+            if !size.is_finite() || size <= 0.0 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid point size {}, expected a positive number.",
+                    size
+                )));
+            }
+
+            state.borrow_mut().point_size = size;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_point_size =
+        arguments::function(lua, "pesto.graphics.getPointSize", move |_, ()| {
+            Ok(state.borrow().point_size)
+        })
+        .unwrap();
+
+    // Points are squares centered on each coordinate, macroquad batches
+    // consecutive shapes into a single draw call so thousands stay cheap
+    let state = graphics_state.clone();
+    let graphics_points = arguments::function(
+        lua,
+        "pesto.graphics.points",
+        move |_, coords: Variadic<f32>| {
+            let points = parse_points(&coords, 0)?;
+
+            let state = state.borrow();
+            let half_size = state.point_size * 0.5;
+
+            for point in points {
+                state.count_draw(DrawSource::Shapes, 4);
+                draw_rectangle(
+                    point.x - half_size,
+                    point.y - half_size,
+                    state.point_size,
+                    state.point_size,
+                    state.color,
+                );
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_line = arguments::function(
+        lua,
+        "pesto.graphics.line",
+        move |_, coords: Variadic<f32>| {
+            let points = parse_points(&coords, 2)?;
+
+            draw_stroke(&points, false, &state.borrow());
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Images are read and decoded synchronously so newImage can be called
+    // from anywhere, though loading them once in pesto.load is recommended
+    let image_project = project.clone();
+    let state = graphics_state.clone();
+    let graphics_new_image = arguments::function(
+        lua,
+        "pesto.graphics.newImage",
+        move |_, (path, filter): (String, Option<String>)| {
+            let filter = match filter {
+                Some(filter) => parse_filter(&filter)?,
+                None => state.borrow().default_filter,
+            };
+
+            let bytes = image_project.read(&path).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not read image '{}': {}", path, err))
+            })?;
+
+            let image = Image::from_file_with_format(&bytes, None).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not decode image '{}': {}", path, err))
+            })?;
+
+            let texture = Texture2D::from_image(&image);
+            texture.set_filter(filter);
+
+            Ok(Texture(texture))
+        },
+    )
+    .unwrap();
+
+    // Files are still read right away, decoding them is what takes time
+    let image_project = project.clone();
+    let state = graphics_state.clone();
+    let graphics_new_image_async = arguments::function(
+        lua,
+        "pesto.graphics.newImageAsync",
+        move |_, (path, filter): (String, Option<String>)| {
+            let mut state = state.borrow_mut();
+
+            let filter = match filter {
+                Some(filter) => parse_filter(&filter)?,
+                None => state.default_filter,
+            };
+
+            let bytes = image_project.read(&path).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not read image '{}': {}", path, err))
+            })?;
+
+            let (sender, receiver) = mpsc::channel();
+
+            thread::spawn(move || {
+                let result =
+                    Image::from_file_with_format(&bytes, None).map_err(|err| err.to_string());
+                let _ = sender.send(result);
+            });
+
+            let handle = Rc::new(RefCell::new(ImageLoad::Pending));
+
+            state.pending_images.push(PendingImage {
+                path,
+                filter,
+                receiver,
+                handle: handle.clone(),
+            });
+            state.images_started += 1;
+
+            Ok(AsyncImage(handle))
+        },
+    )
+    .unwrap();
+
+    // Only affects images and canvases created afterwards
+    let state = graphics_state.clone();
+    let graphics_set_default_filter = arguments::function(
+        lua,
+        "pesto.graphics.setDefaultFilter",
+        move |_, filter: String| {
+            state.borrow_mut().default_filter = parse_filter(&filter)?;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_default_filter =
+        arguments::function(lua, "pesto.graphics.getDefaultFilter", move |_, ()| {
+            Ok(match state.borrow().default_filter {
+                FilterMode::Nearest => "nearest",
+                FilterMode::Linear => "linear",
+            })
+        })
+        .unwrap();
+
+    let graphics_new_quad = arguments::function(
+        lua,
+        "pesto.graphics.newQuad",
+        |_, (x, y, w, h, image_w, image_h): (f32, f32, f32, f32, f32, f32)| {
+            if x < 0.0 || y < 0.0 || w <= 0.0 || h <= 0.0 || x + w > image_w || y + h > image_h {
+                return Err(LuaError::RuntimeError(format!(
+                    "Quad ({}, {}, {}, {}) is outside of the {}x{} image.",
+                    x, y, w, h, image_w, image_h
+                )));
+            }
+
+            Ok(Quad {
+                source: Rect::new(x, y, w, h),
+                reference: vec2(image_w, image_h),
+            })
+        },
+    )
+    .unwrap();
+
+    // Accepts draw(image, x, y, r, sx, sy) and draw(image, quad, x, y, r, sx, sy)
+    let state = graphics_state.clone();
+    let graphics_draw = arguments::function(
+        lua,
+        "pesto.graphics.draw",
+        move |lua, (image, args): (LuaUserDataRef<Texture>, LuaMultiValue)| {
+            let mut args = args.into_vec();
+
+            let quad = match args.first() {
+                Some(LuaValue::UserData(quad)) if quad.is::<Quad>() => {
+                    let quad = quad.borrow::<Quad>()?.clone();
+                    args.remove(0);
+                    Some(quad)
+                }
+                _ => None,
+            };
+
+            let (x, y, rotation, scale_x, scale_y): (
+                f32,
+                f32,
+                Option<f32>,
+                Option<f32>,
+                Option<f32>,
+            ) = lua.unpack_multi(LuaMultiValue::from_vec(args))?;
+
+            let scale_x = scale_x.unwrap_or(1.0);
+            let scale_y = scale_y.unwrap_or(scale_x);
+
+            let source = quad.map(|quad| quad_source(&quad, &image.0));
+
+            draw_image(
+                &image.0,
+                source,
+                x,
+                y,
+                rotation.unwrap_or(0.0),
+                vec2(scale_x, scale_y),
+                &state.borrow(),
+            );
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_new_sprite_batch = arguments::function(
+        lua,
+        "pesto.graphics.newSpriteBatch",
+        move |_, image: LuaUserDataRef<Texture>| {
+            Ok(SpriteBatch {
+                texture: image.0.clone(),
+                vertices: Vec::new(),
+                graphics_state: state.clone(),
+            })
+        },
+    )
+    .unwrap();
+
+    // Durations are either one number for every frame or a table with one
+    // entry per frame, in seconds
+    let state = graphics_state.clone();
+    let graphics_new_animation = arguments::function(
+        lua,
+        "pesto.graphics.newAnimation",
+        move |lua,
+              (image, frame_w, frame_h, durations, looping): (
+            LuaUserDataRef<Texture>,
+            f32,
+            f32,
+            LuaValue,
+            Option<bool>,
+        )| {
+            let size = image.0.size();
+
+            if !(frame_w.is_finite() && frame_h.is_finite())
+                || frame_w <= 0.0
+                || frame_h <= 0.0
+                || frame_w > size.x
+                || frame_h > size.y
+            {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid {}x{} frames for the {}x{} image.",
+                    frame_w, frame_h, size.x, size.y
+                )));
+            }
+
+            let columns = (size.x / frame_w) as usize;
+            let rows = (size.y / frame_h) as usize;
+
+            let frames: Vec<Rect> = (0..rows)
+                .flat_map(|row| {
+                    (0..columns).map(move |column| {
+                        Rect::new(
+                            column as f32 * frame_w,
+                            row as f32 * frame_h,
+                            frame_w,
+                            frame_h,
+                        )
+                    })
+                })
+                .collect();
+
+            if frames.is_empty() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid {}x{} frames for the {}x{} image.",
+                    frame_w, frame_h, size.x, size.y
+                )));
+            }
+
+            let durations = match durations {
+                LuaValue::Table(table) => table
+                    .sequence_values::<f32>()
+                    .collect::<LuaResult<Vec<f32>>>()?,
+                duration => vec![f32::from_lua(duration, lua)?; frames.len()],
+            };
+
+            if durations.len() != frames.len() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Got {} durations for {} frames.",
+                    durations.len(),
+                    frames.len()
+                )));
+            }
+
+            if let Some(duration) = durations
+                .iter()
+                .find(|duration| !(duration.is_finite() && **duration > 0.0))
+            {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid frame duration {}, must be positive.",
+                    duration
+                )));
+            }
+
+            Ok(Animation {
+                texture: image.0.clone(),
+                frames,
+                durations,
+                looping: looping.unwrap_or(true),
+                frame: 0,
+                elapsed: 0.0,
+                graphics_state: state.clone(),
+            })
+        },
+    )
+    .unwrap();
+
+    // Fonts are loaded synchronously like images, best done in pesto.load
+    let font_project = project.clone();
+    let graphics_new_font = arguments::function(
+        lua,
+        "pesto.graphics.newFont",
+        move |_, (path, size): (String, Option<u16>)| {
+            let bytes = font_project.read(&path).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not read font '{}': {}", path, err))
+            })?;
+
+            let font = load_ttf_font_from_bytes(&bytes).map_err(|err| {
+                LuaError::RuntimeError(format!("Could not decode font '{}': {}", path, err))
+            })?;
+
+            Ok(FontHandle {
+                font,
+                size: size.unwrap_or(32),
+            })
+        },
+    )
+    .unwrap();
+
+    // A number only changes the size, a font switches to it at its size
+    let state = graphics_state.clone();
+    let graphics_set_font =
+        arguments::function(lua, "pesto.graphics.setFont", move |lua, font: LuaValue| {
+            let mut state = state.borrow_mut();
+
+            match font {
+                LuaValue::UserData(font) => {
+                    let font = font.borrow::<FontHandle>()?;
+
+                    state.font = Some(font.font.clone());
+                    state.font_size = font.size;
+                }
+                font => {
+                    state.font_size = u16::from_lua(font, lua)?;
+                }
+            }
+
+            Ok(())
+        })
+        .unwrap();
+
+    // The current font at the current size, nil while the default font is
+    // in use
+    let state = graphics_state.clone();
+    let graphics_get_font = arguments::function(lua, "pesto.graphics.getFont", move |_, ()| {
+        let state = state.borrow();
+
+        Ok(state.font.clone().map(|font| FontHandle {
+            font,
+            size: state.font_size,
+        }))
+    })
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_font_height =
+        arguments::function(lua, "pesto.graphics.getFontHeight", move |_, ()| {
+            let state = state.borrow();
+
+            Ok(font_height(state.font.as_ref(), state.font_size))
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_text_width = arguments::function(
+        lua,
+        "pesto.graphics.getTextWidth",
+        move |_, text: String| {
+            let state = state.borrow();
+
+            Ok(text_size(&text, state.font.as_ref(), state.font_size).x)
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_text_size =
+        arguments::function(lua, "pesto.graphics.getTextSize", move |_, text: String| {
+            let state = state.borrow();
+            let size = text_size(&text, state.font.as_ref(), state.font_size);
+
+            Ok((size.x, size.y))
+        })
+        .unwrap();
+
+    // Text is positioned by its baseline, like macroquad's draw_text
+    let state = graphics_state.clone();
+    let graphics_print = arguments::function(
+        lua,
+        "pesto.graphics.print",
+        move |_, (text, x, y): (String, f32, f32)| {
+            let state = state.borrow();
+            let size = state.font_size;
+
+            for (i, line) in text.lines().enumerate() {
+                state.count_draw(DrawSource::Text, glyph_vertices(line));
+                draw_text_ex(
+                    line,
+                    x,
+                    y + i as f32 * size as f32,
+                    TextParams {
+                        font: state.font.as_ref(),
+                        font_size: size,
+                        color: state.color,
+                        ..Default::default()
+                    },
+                );
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_printf = arguments::function(
+        lua,
+        "pesto.graphics.printf",
+        move |_, (text, x, y, limit, align): (String, f32, f32, f32, Option<String>)| {
+            let align = align.unwrap_or("left".to_string());
+
+            if !["left", "center", "right"].contains(&align.as_str()) {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid alignment '{}', expected 'left', 'center' or 'right'.",
+                    align
+                )));
+            }
+
+            let state = state.borrow();
+            let size = state.font_size;
+
+            let font = state.font.as_ref();
+
+            for (i, line) in wrap_text(&text, limit, font, size).iter().enumerate() {
+                let width = measure_text(line, font, size, 1.0).width;
+
+                let offset = match align.as_str() {
+                    "center" => (limit - width) * 0.5,
+                    "right" => limit - width,
+                    _ => 0.0,
+                };
+
+                state.count_draw(DrawSource::Text, glyph_vertices(line));
+                draw_text_ex(
+                    line,
+                    x + offset,
+                    y + i as f32 * size as f32,
+                    TextParams {
+                        font,
+                        font_size: size,
+                        color: state.color,
+                        ..Default::default()
+                    },
+                );
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_new_canvas = arguments::function(
+        lua,
+        "pesto.graphics.newCanvas",
+        move |_, (width, height): (u32, u32)| {
+            if width < 1 || height < 1 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid canvas size {}x{}.",
+                    width, height
+                )));
+            }
+
+            let target = render_target(width, height);
+            target.texture.set_filter(state.borrow().default_filter);
+
+            // Unlike the letterbox target the canvas is rendered upright, so
+            // it can be drawn back with pesto.graphics.draw as is
+            let camera = Camera2D {
+                target: vec2(width as f32 * 0.5, height as f32 * 0.5),
+                zoom: vec2(2.0 / width as f32, 2.0 / height as f32),
+                render_target: Some(target.clone()),
+                ..Default::default()
+            };
+
+            Ok(Canvas { target, camera })
+        },
+    )
+    .unwrap();
+
+    // Layers are drawn over the screen in ascending order once draw returns,
+    // nil goes back to drawing straight onto the screen below them
+    let state = graphics_state.clone();
+    let graphics_set_layer = arguments::function(
+        lua,
+        "pesto.graphics.setLayer",
+        move |_, layer: Option<i32>| {
+            let mut state = state.borrow_mut();
+
+            if let Some(index) = layer {
+                layer_target(&mut state, index)?;
+            }
+
+            state.layer = layer;
+            state.canvas_height = None;
+
+            if let Some(camera) = drawing_camera(&state) {
+                set_camera(&camera);
+            }
+
+            apply_scissor(&state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_layer = arguments::function(lua, "pesto.graphics.getLayer", move |_, ()| {
+        Ok(state.borrow().layer)
+    })
+    .unwrap();
+
+    // Switching back restores the camera of the current layer, or of the
+    // letterbox target that the main loop set up for this frame. The
+    // scissor stays set and is moved over to the new target
+    let state = graphics_state.clone();
+    let graphics_set_canvas = arguments::function(
+        lua,
+        "pesto.graphics.setCanvas",
+        move |_, canvas: Option<LuaUserDataRef<Canvas>>| {
+            let mut state = state.borrow_mut();
+
+            match canvas {
+                Some(canvas) => {
+                    set_camera(&canvas.camera);
+                    state.canvas_height = Some(canvas.target.texture.height() as i32);
+                }
+                None => {
+                    if let Some(camera) = drawing_camera(&state) {
+                        set_camera(&camera);
+                    }
+
+                    state.canvas_height = None;
+                }
+            }
+
+            apply_scissor(&state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Draws are batched, so they're flushed first to capture everything
+    // drawn this frame so far. Rows come back bottom up and the letterbox
+    // target is drawn flipped, so reversing them gives the upright image
+    let directory = save_directory.to_path_buf();
+    let state = graphics_state.clone();
+    let graphics_capture_screenshot = arguments::function(
+        lua,
+        "pesto.graphics.captureScreenshot",
+        move |_, path: String| {
+            let file_path = filesystem::save_path(&directory, &path)?;
+
+            let state = state.borrow();
+            let target = state
+                .screen_target
+                .as_ref()
+                .ok_or_else(|| LuaError::RuntimeError("No frame to capture yet.".to_string()))?;
+
+            unsafe { get_internal_gl() }.flush();
+
+            let image = target.texture.get_texture_data();
+            let row = image.width as usize * 4;
+            let bytes: Vec<u8> = image
+                .bytes
+                .chunks_exact(row)
+                .rev()
+                .flatten()
+                .copied()
+                .collect();
+
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent).map_err(|err| {
+                    LuaError::RuntimeError(format!("Could not save screenshot '{}': {}", path, err))
+                })?;
+            }
+
+            image::save_buffer_with_format(
+                file_path,
+                &bytes,
+                image.width as u32,
+                image.height as u32,
+                image::ColorType::Rgba8,
+                image::ImageFormat::Png,
+            )
+            .map_err(|err| {
+                LuaError::RuntimeError(format!("Could not save screenshot '{}': {}", path, err))
+            })
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_virtual_resolution = arguments::function(
+        lua,
+        "pesto.graphics.setVirtualResolution",
+        move |_, (width, height): (f32, f32)| {
+            let mut state = state.borrow_mut();
+            let max_size = state.max_texture_size.unwrap_or(f32::MAX);
+
+            let valid = |size: f32| size >= 1.0 && size.is_finite() && size <= max_size;
+
+            if !(valid(width) && valid(height)) {
+                let expected = match state.max_texture_size {
+                    Some(max_size) => format!("from 1 to {}", max_size),
+                    None => "of at least 1".to_string(),
+                };
+
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid virtual resolution {}x{}, expected sizes {}.",
+                    width, height, expected
+                )));
+            }
+
+            state.virtual_resolution = vec2(width, height);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_set_pixel_perfect = arguments::function(
+        lua,
+        "pesto.graphics.setPixelPerfect",
+        move |_, enabled: bool| {
+            state.borrow_mut().pixel_perfect = enabled;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_is_pixel_perfect =
+        arguments::function(lua, "pesto.graphics.isPixelPerfect", move |_, ()| {
+            Ok(state.borrow().pixel_perfect)
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_dimensions =
+        arguments::function(lua, "pesto.graphics.getDimensions", move |_, ()| {
+            let resolution = state.borrow().virtual_resolution;
+
+            Ok((resolution.x, resolution.y))
+        })
+        .unwrap();
+
+    // Counts what pesto drew so far this frame, each shape, image, line of
+    // text and sprite batch chunk is a draw before macroquad batches them
+    let state = graphics_state.clone();
+    let graphics_get_stats = arguments::function(lua, "pesto.graphics.getStats", move |lua, ()| {
+        let stats = state.borrow().stats.get();
+
+        let table = lua.create_table()?;
+        table.set("drawcalls", stats.draw_calls)?;
+        table.set("vertices", stats.vertices)?;
+        table.set("textureswitches", stats.texture_switches)?;
+
+        Ok(table)
+    })
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_push = arguments::function(lua, "pesto.graphics.push", move |_, ()| {
+        let mut state = state.borrow_mut();
+
+        let transform = state.transform;
+        state.transform_stack.push(transform);
+
+        Ok(())
+    })
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_pop = arguments::function(lua, "pesto.graphics.pop", move |_, ()| {
+        let mut state = state.borrow_mut();
+
+        state.transform = state.transform_stack.pop().ok_or_else(|| {
+            LuaError::RuntimeError("pop called without a matching push.".to_string())
+        })?;
+
+        apply_transform(&state);
+
+        Ok(())
+    })
+    .unwrap();
+
+    // Like push and pop but for the color, line style, point size, font,
+    // blend mode and scissor, so libraries can draw without leaking their
+    // settings into the game's draws
+    let state = graphics_state.clone();
+    let graphics_save_state = arguments::function(lua, "pesto.graphics.saveState", move |_, ()| {
+        let mut state = state.borrow_mut();
+
+        let saved = SavedState {
+            color: state.color,
+            line_width: state.line_width,
+            line_style: state.line_style,
+            dash_length: state.dash_length,
+            point_size: state.point_size,
+            font: state.font.clone(),
+            font_size: state.font_size,
+            blend_mode: state.blend_mode,
+            scissor: state.scissor,
+        };
+        state.saved_states.push(saved);
+
+        Ok(())
+    })
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_restore_state =
+        arguments::function(lua, "pesto.graphics.restoreState", move |_, ()| {
+            let mut state = state.borrow_mut();
+
+            let saved = state.saved_states.pop().ok_or_else(|| {
+                LuaError::RuntimeError(
+                    "restoreState called without a matching saveState.".to_string(),
+                )
+            })?;
+
+            state.color = saved.color;
+            state.line_width = saved.line_width;
+            state.line_style = saved.line_style;
+            state.dash_length = saved.dash_length;
+            state.point_size = saved.point_size;
+            state.font = saved.font;
+            state.font_size = saved.font_size;
+            state.blend_mode = saved.blend_mode;
+            state.scissor = saved.scissor;
+
+            apply_scissor(&state);
+            apply_material(&mut state);
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_translate = arguments::function(
+        lua,
+        "pesto.graphics.translate",
+        move |_, (dx, dy): (f32, f32)| {
+            let mut state = state.borrow_mut();
+
+            state.transform *= Mat4::from_translation(vec3(dx, dy, 0.0));
+            apply_transform(&state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_rotate =
+        arguments::function(lua, "pesto.graphics.rotate", move |_, angle: f32| {
+            let mut state = state.borrow_mut();
+
+            state.transform *= Mat4::from_rotation_z(angle);
+            apply_transform(&state);
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_scale = arguments::function(
+        lua,
+        "pesto.graphics.scale",
+        move |_, (sx, sy): (f32, Option<f32>)| {
+            let mut state = state.borrow_mut();
+
+            state.transform *= Mat4::from_scale(vec3(sx, sy.unwrap_or(sx), 1.0));
+            apply_transform(&state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // The camera sits underneath push, pop and the other transforms, and
+    // like them only lasts until the end of the frame
+    let state = graphics_state.clone();
+    let graphics_set_camera = arguments::function(
+        lua,
+        "pesto.graphics.setCamera",
+        move |_, (x, y, zoom, rotation): (f32, f32, Option<f32>, Option<f32>)| {
+            let zoom = zoom.unwrap_or(1.0);
+
+            if !zoom.is_finite() || zoom <= 0.0 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid camera zoom: {}, must be positive.",
+                    zoom
+                )));
+            }
+
+            let mut state = state.borrow_mut();
+
+            state.camera = camera_matrix(
+                state.virtual_resolution,
+                x,
+                y,
+                zoom,
+                rotation.unwrap_or(0.0),
+            );
+            apply_transform(&state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_reset_camera =
+        arguments::function(lua, "pesto.graphics.resetCamera", move |_, ()| {
+            let mut state = state.borrow_mut();
+
+            state.camera = Mat4::IDENTITY;
+            apply_transform(&state);
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_to_world = arguments::function(
+        lua,
+        "pesto.graphics.toWorld",
+        move |_, (sx, sy): (f32, f32)| {
+            let world = state
+                .borrow()
+                .camera
+                .inverse()
+                .transform_point3(vec3(sx, sy, 0.0));
+
+            Ok((world.x, world.y))
+        },
+    )
+    .unwrap();
+
+    // The render target matches the virtual resolution, so virtual
+    // coordinates are already render target pixels, and canvas coordinates
+    // are canvas pixels
+    let state = graphics_state.clone();
+    let graphics_set_scissor = arguments::function(
+        lua,
+        "pesto.graphics.setScissor",
+        move |_, (x, y, w, h): (f32, f32, f32, f32)| {
+            let mut state = state.borrow_mut();
+
+            state.scissor = Some((x as i32, y as i32, w.max(0.0) as i32, h.max(0.0) as i32));
+            apply_scissor(&state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_clear_scissor =
+        arguments::function(lua, "pesto.graphics.clearScissor", move |_, ()| {
+            let mut state = state.borrow_mut();
+
+            state.scissor = None;
+            apply_scissor(&state);
+
+            Ok(())
+        })
+        .unwrap();
+
+    // Blend modes replace the active shader, like setShader replaces the
+    // blend mode
+    let state = graphics_state.clone();
+    let graphics_set_blend_mode = arguments::function(
+        lua,
+        "pesto.graphics.setBlendMode",
+        move |_, mode: String| {
+            let mut state = state.borrow_mut();
+
+            state.blend_mode = parse_blend_mode(&mode)?;
+            apply_material(&mut state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_blend_mode =
+        arguments::function(lua, "pesto.graphics.getBlendMode", move |_, ()| {
+            Ok(match state.borrow().blend_mode {
+                BlendMode::Alpha => "alpha",
+                BlendMode::Add => "add",
+                BlendMode::Multiply => "multiply",
+                BlendMode::Screen => "screen",
+            })
+        })
+        .unwrap();
+
+    // Shaders always alpha blend so translucent draws keep working, and
+    // compile errors are passed back to Lua as they are
+    let graphics_new_shader = arguments::function(
+        lua,
+        "pesto.graphics.newShader",
+        |_, (vertex, fragment): (String, String)| {
+            let uniforms = parse_uniforms(&[&vertex, &fragment]);
+
+            let material = load_material(
+                ShaderSource::Glsl {
+                    vertex: &vertex,
+                    fragment: &fragment,
+                },
+                MaterialParams {
+                    pipeline_params: PipelineParams {
+                        color_blend: Some(blend_state(BlendMode::Alpha)),
+                        ..Default::default()
+                    },
+                    uniforms: uniforms.clone(),
+                    ..Default::default()
+                },
+            )
+            .map_err(|err| LuaError::RuntimeError(format!("Could not compile shader: {}", err)))?;
+
+            Ok(Shader { material, uniforms })
+        },
+    )
+    .unwrap();
+
+    // Resetting the shader goes back to the current blend mode or stencil
+    // test
+    let state = graphics_state.clone();
+    let graphics_set_shader = arguments::function(
+        lua,
+        "pesto.graphics.setShader",
+        move |_, shader: Option<LuaUserDataRef<Shader>>| {
+            match shader {
+                Some(shader) => gl_use_material(&shader.material),
+                None => apply_material(&mut state.borrow_mut()),
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // The mask is drawn in screen space with the current camera and
+    // transform, and drawing goes back to the current layer or the screen
+    // afterwards. No borrow is held while the function runs so it can call
+    // anything in pesto.graphics
+    let state = graphics_state.clone();
+    let graphics_stencil = arguments::function(
+        lua,
+        "pesto.graphics.stencil",
+        move |_,
+              (func, action, value, keep): (
+            LuaFunction,
+            Option<String>,
+            Option<i64>,
+            Option<bool>,
+        )| {
+            let action = parse_stencil_action(action.as_deref().unwrap_or("replace"))?;
+            let value = stencil_value(value.unwrap_or(1))?;
+
+            {
+                let mut state = state.borrow_mut();
+                let stencil = stencil_mask(&mut state, !keep.unwrap_or(false))?;
+
+                let (_, material) = stencil
+                    .write_materials
+                    .iter()
+                    .find(|(other, _)| *other == action)
+                    .unwrap();
+
+                material.set_uniform("Value", value as f32 / 255.0);
+
+                set_camera(&letterbox_camera(&stencil.target));
+                gl_use_material(material);
+            }
+
+            let result = func.call::<_, ()>(());
+
+            let mut state = state.borrow_mut();
+
+            if let Some(camera) = drawing_camera(&state) {
+                set_camera(&camera);
+            }
+
+            apply_material(&mut state);
+
+            result
+        },
+    )
+    .unwrap();
+
+    // Like shaders, the stencil test replaces the blend mode until it's
+    // disabled by calling this without a comparison or with "always"
+    let state = graphics_state.clone();
+    let graphics_set_stencil_test = arguments::function(
+        lua,
+        "pesto.graphics.setStencilTest",
+        move |_, (compare, value): (Option<String>, Option<i64>)| {
+            let mut state = state.borrow_mut();
+
+            state.stencil_test = match compare.filter(|compare| compare != "always") {
+                Some(compare) => {
+                    let compare = parse_stencil_compare(&compare)?;
+                    let value = stencil_value(value.unwrap_or(0))?;
+
+                    stencil_mask(&mut state, false)?;
+
+                    Some((compare, value))
+                }
+                None => None,
+            };
+
+            apply_material(&mut state);
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = graphics_state.clone();
+    let graphics_get_stencil_test =
+        arguments::function(lua, "pesto.graphics.getStencilTest", move |_, ()| {
+            Ok(match state.borrow().stencil_test {
+                Some((compare, value)) => (
+                    match compare {
+                        StencilCompare::Equal => "equal",
+                        StencilCompare::NotEqual => "notequal",
+                        StencilCompare::Less => "less",
+                        StencilCompare::LessEqual => "lequal",
+                        StencilCompare::Greater => "greater",
+                        StencilCompare::GreaterEqual => "gequal",
+                    },
+                    value,
+                ),
+                None => ("always", 0),
+            })
+        })
+        .unwrap();
+
+    let graphics_send = arguments::function(
+        lua,
+        "pesto.graphics.send",
+        |_, (shader, name, value): (LuaUserDataRef<Shader>, String, LuaValue)| {
+            let kind = shader
+                .uniforms
+                .iter()
+                .find(|(other, _)| *other == name)
+                .map(|(_, kind)| *kind)
+                .ok_or_else(|| {
+                    LuaError::RuntimeError(format!("Shader has no uniform '{}'.", name))
+                })?;
+
+            let values: Vec<f32> = match value {
+                LuaValue::Integer(value) => vec![value as f32],
+                LuaValue::Number(value) => vec![value as f32],
+                LuaValue::Table(table) => table
+                    .sequence_values::<f32>()
+                    .collect::<LuaResult<Vec<f32>>>()?,
+                _ => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Uniform '{}' expects a number or a table of numbers.",
+                        name
+                    )))
+                }
+            };
+
+            let material = &shader.material;
+
+            match (kind, values.as_slice()) {
+                (UniformType::Float1, &[x]) => material.set_uniform(&name, x),
+                (UniformType::Float2, &[x, y]) => material.set_uniform(&name, vec2(x, y)),
+                (UniformType::Float3, &[x, y, z]) => material.set_uniform(&name, vec3(x, y, z)),
+                (UniformType::Float4, &[x, y, z, w]) => {
+                    material.set_uniform(&name, vec4(x, y, z, w))
+                }
+                _ => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Uniform '{}' expects {} values, got {}.",
+                        name,
+                        kind.size() / 4,
+                        values.len()
+                    )))
+                }
+            }
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    graphics_table.set("setColor", graphics_set_color).unwrap();
+    graphics_table.set("getColor", graphics_get_color).unwrap();
+    graphics_table.set("hsv", graphics_hsv).unwrap();
+    graphics_table.set("hsl", graphics_hsl).unwrap();
+    graphics_table
+        .set("setBackgroundColor", graphics_set_background_color)
+        .unwrap();
+    graphics_table
+        .set("setLetterboxColor", graphics_set_letterbox_color)
+        .unwrap();
+    graphics_table.set("circle", graphics_circle).unwrap();
+    graphics_table.set("rectangle", graphics_rectangle).unwrap();
+    graphics_table
+        .set("newNineSlice", graphics_new_nine_slice)
+        .unwrap();
+    graphics_table
+        .set("drawNineSlice", graphics_draw_nine_slice)
+        .unwrap();
+    graphics_table
+        .set("rectangleGradient", graphics_rectangle_gradient)
+        .unwrap();
+    graphics_table.set("triangle", graphics_triangle).unwrap();
+    graphics_table.set("polygon", graphics_polygon).unwrap();
+    graphics_table.set("ellipse", graphics_ellipse).unwrap();
+    graphics_table.set("arc", graphics_arc).unwrap();
+    graphics_table
+        .set("setLineWidth", graphics_set_line_width)
+        .unwrap();
+    graphics_table
+        .set("getLineWidth", graphics_get_line_width)
+        .unwrap();
+    graphics_table
+        .set("setLineStyle", graphics_set_line_style)
+        .unwrap();
+    graphics_table
+        .set("getLineStyle", graphics_get_line_style)
+        .unwrap();
+    graphics_table.set("line", graphics_line).unwrap();
+    graphics_table
+        .set("setPointSize", graphics_set_point_size)
+        .unwrap();
+    graphics_table
+        .set("getPointSize", graphics_get_point_size)
+        .unwrap();
+    graphics_table.set("points", graphics_points).unwrap();
+    graphics_table.set("newImage", graphics_new_image).unwrap();
+    graphics_table
+        .set("newImageAsync", graphics_new_image_async)
+        .unwrap();
+    graphics_table
+        .set("setDefaultFilter", graphics_set_default_filter)
+        .unwrap();
+    graphics_table
+        .set("getDefaultFilter", graphics_get_default_filter)
+        .unwrap();
+    graphics_table.set("newQuad", graphics_new_quad).unwrap();
+    graphics_table
+        .set("newAnimation", graphics_new_animation)
+        .unwrap();
+    graphics_table
+        .set("newSpriteBatch", graphics_new_sprite_batch)
+        .unwrap();
+    graphics_table.set("draw", graphics_draw).unwrap();
+    graphics_table.set("setFont", graphics_set_font).unwrap();
+    graphics_table.set("newFont", graphics_new_font).unwrap();
+    graphics_table.set("getFont", graphics_get_font).unwrap();
+    graphics_table
+        .set("getFontHeight", graphics_get_font_height)
+        .unwrap();
+    graphics_table
+        .set("getTextWidth", graphics_get_text_width)
+        .unwrap();
+    graphics_table
+        .set("getTextSize", graphics_get_text_size)
+        .unwrap();
+    graphics_table.set("print", graphics_print).unwrap();
+    graphics_table.set("printf", graphics_printf).unwrap();
+    graphics_table
+        .set("newCanvas", graphics_new_canvas)
+        .unwrap();
+    graphics_table
+        .set("setCanvas", graphics_set_canvas)
+        .unwrap();
+    graphics_table.set("setLayer", graphics_set_layer).unwrap();
+    graphics_table.set("getLayer", graphics_get_layer).unwrap();
+    graphics_table
+        .set("captureScreenshot", graphics_capture_screenshot)
+        .unwrap();
+    graphics_table
+        .set("setVirtualResolution", graphics_set_virtual_resolution)
+        .unwrap();
+    graphics_table
+        .set("getDimensions", graphics_get_dimensions)
+        .unwrap();
+    graphics_table.set("getStats", graphics_get_stats).unwrap();
+    graphics_table
+        .set("setPixelPerfect", graphics_set_pixel_perfect)
+        .unwrap();
+    graphics_table
+        .set("isPixelPerfect", graphics_is_pixel_perfect)
+        .unwrap();
+
+    graphics_table
+        .set("setBlendMode", graphics_set_blend_mode)
+        .unwrap();
+    graphics_table
+        .set("getBlendMode", graphics_get_blend_mode)
+        .unwrap();
+
+    graphics_table
+        .set("newShader", graphics_new_shader)
+        .unwrap();
+    graphics_table
+        .set("setShader", graphics_set_shader)
+        .unwrap();
+    graphics_table.set("stencil", graphics_stencil).unwrap();
+    graphics_table
+        .set("setStencilTest", graphics_set_stencil_test)
+        .unwrap();
+    graphics_table
+        .set("getStencilTest", graphics_get_stencil_test)
+        .unwrap();
+    graphics_table.set("send", graphics_send).unwrap();
+
+    graphics_table.set("push", graphics_push).unwrap();
+    graphics_table.set("pop", graphics_pop).unwrap();
+    graphics_table
+        .set("saveState", graphics_save_state)
+        .unwrap();
+    graphics_table
+        .set("restoreState", graphics_restore_state)
+        .unwrap();
+    graphics_table.set("translate", graphics_translate).unwrap();
+    graphics_table.set("rotate", graphics_rotate).unwrap();
+    graphics_table.set("scale", graphics_scale).unwrap();
+    graphics_table
+        .set("setCamera", graphics_set_camera)
+        .unwrap();
+    graphics_table
+        .set("resetCamera", graphics_reset_camera)
+        .unwrap();
+    graphics_table.set("toWorld", graphics_to_world).unwrap();
+
+    graphics_table
+        .set("setScissor", graphics_set_scissor)
+        .unwrap();
+    graphics_table
+        .set("clearScissor", graphics_clear_scissor)
+        .unwrap();
+
+    pesto_table.set("graphics", graphics_table).unwrap();
+}
@@ -0,0 +1,249 @@
+use crate::{arguments, encoding, filesystem, project::Project};
+use mlua::prelude::*;
+use std::{fs, path::Path, rc::Rc};
+
+// Per round shift amounts of MD5
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+// Per round constants of MD5, the sines of the round numbers scaled to 32
+// bits
+const MD5_ROUNDS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+// First 32 bits of the fractional parts of the cube roots of the first 64
+// primes
+const SHA256_ROUNDS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// Both hashes work on 64 byte blocks, the message is followed by a one bit,
+// zeros and its length in bits
+fn pad(data: &[u8], big_endian: bool) -> Vec<u8> {
+    let bits = (data.len() as u64).wrapping_mul(8);
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    if big_endian {
+        message.extend_from_slice(&bits.to_be_bytes());
+    } else {
+        message.extend_from_slice(&bits.to_le_bytes());
+    }
+
+    message
+}
+
+fn md5(data: &[u8]) -> Vec<u8> {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    for block in pad(data, false).chunks(64) {
+        let words: Vec<u32> = block
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for (i, shift) in MD5_SHIFTS.iter().enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_ROUNDS[i])
+                .wrapping_add(words[g])
+                .rotate_left(*shift);
+
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+
+        for (value, added) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    state.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    for block in pad(data, true).chunks(64) {
+        let mut words = [0u32; 64];
+
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        for i in 16..64 {
+            let s0 = words[i - 15].rotate_right(7)
+                ^ words[i - 15].rotate_right(18)
+                ^ (words[i - 15] >> 3);
+            let s1 = words[i - 2].rotate_right(17)
+                ^ words[i - 2].rotate_right(19)
+                ^ (words[i - 2] >> 10);
+
+            words[i] = words[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(words[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_ROUNDS[i])
+                .wrapping_add(words[i]);
+
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+
+            (h, g, f, e) = (g, f, e, d.wrapping_add(temp1));
+            (d, c, b, a) = (c, b, a, temp1.wrapping_add(temp2));
+        }
+
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    state.iter().flat_map(|value| value.to_be_bytes()).collect()
+}
+
+// Digest of the data as lowercase hex
+fn hash(algorithm: &str, data: &[u8]) -> LuaResult<String> {
+    let digest = match algorithm {
+        "md5" => md5(data),
+        "sha256" => sha256(data),
+        _ => {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid hash algorithm '{}', expected 'md5' or 'sha256'.",
+                algorithm
+            )))
+        }
+    };
+
+    Ok(encoding::encode_hex(&digest))
+}
+
+// Hashes are added to pesto.utils next to lume's functions, so this has to
+// run after lume is loaded
+pub fn register(lua: &Lua, pesto_table: &LuaTable, project: &Rc<Project>, save_directory: &Path) {
+    let utils_table: LuaTable = pesto_table.get("utils").unwrap();
+
+    let utils_hash = arguments::function(
+        lua,
+        "pesto.utils.hash",
+        |_, (algorithm, data): (String, LuaString)| hash(&algorithm, data.as_bytes()),
+    )
+    .unwrap();
+
+    // Files in the save directory are hashed before project files with the
+    // same path, so save files can be checked as easily as assets
+    let hash_project = project.clone();
+    let directory = save_directory.to_path_buf();
+    let utils_hash_file = arguments::function(
+        lua,
+        "pesto.utils.hashFile",
+        move |_, (algorithm, path): (String, String)| {
+            let save_path = filesystem::save_path(&directory, &path)?;
+
+            let bytes = if save_path.is_file() {
+                fs::read(save_path)
+            } else {
+                hash_project.read(&path)
+            };
+
+            let bytes = bytes.map_err(|err| {
+                LuaError::RuntimeError(format!("Could not read file '{}': {}", path, err))
+            })?;
+
+            hash(&algorithm, &bytes)
+        },
+    )
+    .unwrap();
+
+    utils_table.set("hash", utils_hash).unwrap();
+    utils_table.set("hashFile", utils_hash_file).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from RFC 1321 and FIPS 180-2
+    #[test]
+    fn md5_known_answers() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"", "d41d8cd98f00b204e9800998ecf8427e"),
+            (b"abc", "900150983cd24fb0d6963f7d28e17f72"),
+            (b"message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+
+        for (data, digest) in cases {
+            assert_eq!(hash("md5", data).unwrap(), digest);
+        }
+    }
+
+    #[test]
+    fn sha256_known_answers() {
+        let cases: [(&[u8], &str); 3] = [
+            (
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ];
+
+        for (data, digest) in cases {
+            assert_eq!(hash("sha256", data).unwrap(), digest);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use crate::arguments;
+use mlua::prelude::*;
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    rc::Rc,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+// Connecting, sending and every read give up after this long
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Status, lowercased headers and body of a finished request
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+// Request running on another thread and the lua callback waiting for it
+struct PendingRequest {
+    receiver: mpsc::Receiver<Result<Response, String>>,
+    callback: LuaRegistryKey,
+}
+
+// Rust side http state, requests belong to a single lua state so this is
+// reset on every reload
+#[derive(Default)]
+pub struct HttpState {
+    pending: Vec<PendingRequest>,
+}
+
+// Split an http url into the host, port and path with its query, the
+// fragment is never sent. There is no TLS support, so https urls are
+// rejected instead of being sent in the clear
+fn parse_url(url: &str) -> Result<(String, u16, String), String> {
+    if url.starts_with("https://") {
+        return Err("https is not supported, only http:// urls can be requested".to_string());
+    }
+
+    // Anything that could end the request line early
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("urls can't contain spaces or control characters".to_string());
+    }
+
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| "expected an http:// url".to_string())?;
+
+    let rest = rest.split('#').next().unwrap_or("");
+
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(index) if rest[index..].starts_with('?') => {
+            (&rest[..index], format!("/{}", &rest[index..]))
+        }
+        Some(index) => (&rest[..index], rest[index..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| format!("invalid port '{}'", port))?,
+        ),
+        None => (authority, 80),
+    };
+
+    if host.is_empty() {
+        return Err("missing host".to_string());
+    }
+
+    Ok((host.to_string(), port, path))
+}
+
+// Header names and values are sent as lines of the request, so line breaks
+// in them could add headers or a whole other request
+fn check_header_text(kind: &str, text: &str) -> LuaResult<()> {
+    if text.contains(['\r', '\n']) {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid {} '{}', expected text without line breaks.",
+            kind,
+            text.escape_default()
+        )));
+    }
+
+    Ok(())
+}
+
+// Chunked bodies are a list of hex sizes each followed by that many bytes,
+// ending with an empty chunk
+fn decode_chunked(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|pair| pair == b"\r\n")
+            .ok_or_else(|| "truncated chunked body".to_string())?;
+
+        let size_line = String::from_utf8_lossy(&data[..line_end]);
+        let size_digits = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_digits, 16)
+            .map_err(|_| format!("invalid chunk size '{}'", size_digits))?;
+
+        data = &data[line_end + 2..];
+
+        if size == 0 {
+            return Ok(body);
+        }
+
+        if data.len() < size + 2 {
+            return Err("truncated chunked body".to_string());
+        }
+
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+fn parse_response(data: &[u8]) -> Result<Response, String> {
+    let head_end = data
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| "invalid response".to_string())?;
+
+    let head = String::from_utf8_lossy(&data[..head_end]);
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| "invalid status line".to_string())?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut body = data[head_end + 4..].to_vec();
+
+    if header("transfer-encoding").is_some_and(|encoding| encoding.contains("chunked")) {
+        body = decode_chunked(&body)?;
+    } else if let Some(length) = header("content-length").and_then(|length| length.parse().ok()) {
+        body.truncate(length);
+    }
+
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+// Blocking HTTP/1.1 request, the connection is closed by the server once
+// the response is sent
+fn send_request(
+    url: &str,
+    method: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Result<Response, String> {
+    let (host, port, path) = parse_url(url)?;
+
+    let address = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or_else(|| format!("could not resolve '{}'", host))?;
+
+    let mut stream =
+        TcpStream::connect_timeout(&address, TIMEOUT).map_err(|err| err.to_string())?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|err| err.to_string())?;
+
+    // The port is only left out of the host header when it's the default
+    let host_header = match port {
+        80 => host.clone(),
+        port => format!("{}:{}", host, port),
+    };
+
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: pesto\r\nConnection: close\r\n",
+        method, path, host_header
+    );
+
+    let has_length = headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("content-length"));
+
+    if !has_length && (!body.is_empty() || method != "GET") {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    request.push_str("\r\n");
+
+    let mut data = Vec::new();
+
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|()| stream.write_all(body))
+        .and_then(|()| stream.read_to_end(&mut data))
+        .map_err(|err| err.to_string())?;
+
+    parse_response(&data)
+}
+
+// Call the callbacks of finished requests, called by the main loop every
+// frame. Callbacks can start new requests so none of the state is borrowed
+// while they run
+pub fn poll(lua: &Lua, http_state: &Rc<RefCell<HttpState>>) -> LuaResult<()> {
+    let mut finished = Vec::new();
+
+    {
+        let pending = &mut http_state.borrow_mut().pending;
+        let mut i = 0;
+
+        while i < pending.len() {
+            let result = match pending[i].receiver.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    i += 1;
+                    continue;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => Err("request stopped".to_string()),
+            };
+
+            finished.push((pending.remove(i).callback, result));
+        }
+    }
+
+    for (key, result) in finished {
+        let callback: LuaFunction = lua.registry_value(&key)?;
+        lua.remove_registry_value(key)?;
+
+        match result {
+            Ok(response) => {
+                let headers = lua.create_table_from(response.headers)?;
+
+                let table = lua.create_table()?;
+                table.set("status", response.status)?;
+                table.set("headers", headers)?;
+                table.set("body", lua.create_string(response.body)?)?;
+
+                callback.call::<_, ()>(table)?;
+            }
+            Err(message) => callback.call::<_, ()>((LuaValue::Nil, message))?,
+        }
+    }
+
+    Ok(())
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, http_state: &Rc<RefCell<HttpState>>) {
+    let http_table = lua.create_table().unwrap();
+
+    // request(url, callback) or request(url, options, callback). The
+    // callback runs on a later frame with a response table, or nil and a
+    // message when the request failed
+    let state = http_state.clone();
+    let http_request = arguments::function(
+        lua,
+        "pesto.http.request",
+        move |lua, (url, options, callback): (String, LuaValue, Option<LuaFunction>)| {
+            let (options, callback) = match (options, callback) {
+                (LuaValue::Function(callback), None) => (None, callback),
+                (LuaValue::Nil, Some(callback)) => (None, callback),
+                (LuaValue::Table(options), Some(callback)) => (Some(options), callback),
+                _ => {
+                    return Err(LuaError::RuntimeError(
+                        "Invalid arguments, expected request(url, [options], callback)."
+                            .to_string(),
+                    ))
+                }
+            };
+
+            parse_url(&url)
+                .map_err(|err| LuaError::RuntimeError(format!("Invalid url '{}': {}", url, err)))?;
+
+            let mut method = "GET".to_string();
+            let mut headers = Vec::new();
+            let mut body = Vec::new();
+
+            if let Some(options) = options {
+                if let Some(option) = options.get::<_, Option<String>>("method")? {
+                    // The method starts the request line, so it can't have
+                    // spaces or line breaks in it either
+                    if option.is_empty() || !option.chars().all(|c| c.is_ascii_alphabetic()) {
+                        return Err(LuaError::RuntimeError(format!(
+                            "Invalid method '{}', expected a word like 'GET' or 'POST'.",
+                            option.escape_default()
+                        )));
+                    }
+
+                    method = option.to_uppercase();
+                }
+
+                if let Some(option) = options.get::<_, Option<LuaTable>>("headers")? {
+                    for pair in option.pairs::<String, String>() {
+                        let (name, value) = pair?;
+
+                        check_header_text("header name", &name)?;
+                        check_header_text("header value", &value)?;
+
+                        headers.push((name, value));
+                    }
+                }
+
+                if let Some(option) = options.get::<_, Option<LuaString>>("body")? {
+                    body = option.as_bytes().to_vec();
+                }
+            }
+
+            let (sender, receiver) = mpsc::channel();
+
+            thread::spawn(move || {
+                let _ = sender.send(send_request(&url, &method, &headers, &body));
+            });
+
+            state.borrow_mut().pending.push(PendingRequest {
+                receiver,
+                callback: lua.create_registry_value(callback)?,
+            });
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    http_table.set("request", http_request).unwrap();
+
+    pesto_table.set("http", http_table).unwrap();
+}
@@ -0,0 +1,79 @@
+use std::{fs, path::Path};
+
+// Glob patterns read from .pestoignore in the project root, one per line
+// with # starting a comment. Patterns without a slash match file names in
+// any directory, others match the path relative to the project root
+pub struct IgnoreList {
+    patterns: Vec<String>,
+}
+
+impl IgnoreList {
+    pub fn load(directory: &str) -> Self {
+        let patterns = fs::read_to_string(Path::new(directory).join(".pestoignore"))
+            .map(|contents| {
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.trim_start_matches("./").to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        IgnoreList { patterns }
+    }
+
+    // Patterns as globs rooted in the project directory for luacheck's
+    // --exclude-files, each also covering the contents of a directory
+    pub fn globs(&self, directory: &str) -> Vec<String> {
+        self.patterns
+            .iter()
+            .flat_map(|pattern| {
+                let glob = if pattern.contains('/') {
+                    format!("{}/{}", directory, pattern)
+                } else {
+                    format!("{}/**/{}", directory, pattern)
+                };
+
+                [format!("{}/**", glob), glob]
+            })
+            .collect()
+    }
+
+    // A path is also ignored when one of its parent directories is
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        let path = relative.to_string_lossy().replace('\\', "/");
+        let components: Vec<&str> = path.split('/').collect();
+
+        (1..=components.len()).any(|count| {
+            let prefix = components[..count].join("/");
+            let name = components[count - 1];
+
+            self.patterns.iter().any(|pattern| {
+                if pattern.contains('/') {
+                    glob_match(pattern.as_bytes(), prefix.as_bytes())
+                } else {
+                    glob_match(pattern.as_bytes(), name.as_bytes())
+                }
+            })
+        })
+    }
+}
+
+// * matches within a path component, ** across components and ? a single
+// character
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|i| *i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => !text.is_empty() && text[0] != b'/' && glob_match(rest, &text[1..]),
+        [character, rest @ ..] => text.first() == Some(character) && glob_match(rest, &text[1..]),
+    }
+}
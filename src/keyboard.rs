@@ -0,0 +1,276 @@
+use crate::arguments;
+use macroquad::prelude::*;
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// Rust side keyboard state shared with the main loop
+pub struct KeyboardState {
+    pub text_input: bool,
+    repeat_delay: f32,
+    repeat_interval: f32,
+    // Held keys and the time until they repeat next
+    held: Vec<(KeyCode, f32)>,
+}
+
+impl Default for KeyboardState {
+    fn default() -> Self {
+        KeyboardState {
+            text_input: true,
+            repeat_delay: 0.5,
+            repeat_interval: 0.05,
+            held: Vec::new(),
+        }
+    }
+}
+
+// Keys pressed this frame for pesto.keypressed with whether each press is a
+// repeat, held keys first repeat after the delay and then every interval
+pub fn pressed_keys(state: &mut KeyboardState, dt: f32) -> Vec<(&'static str, bool)> {
+    let mut pressed = Vec::new();
+
+    state.held.retain(|(key, _)| is_key_down(*key));
+
+    for (name, key) in KEYS {
+        if is_key_pressed(*key) {
+            pressed.push((*name, false));
+            state.held.retain(|(held_key, _)| held_key != key);
+            state.held.push((*key, state.repeat_delay));
+        }
+    }
+
+    for (key, remaining) in &mut state.held {
+        if is_key_pressed(*key) {
+            continue;
+        }
+
+        *remaining -= dt;
+
+        while *remaining <= 0.0 {
+            if let Some((name, _)) = KEYS.iter().find(|(_, code)| code == key) {
+                pressed.push((*name, true));
+            }
+
+            *remaining += state.repeat_interval;
+        }
+    }
+
+    pressed
+}
+
+// Characters typed since the last frame that should reach pesto.textinput,
+// the queue is always drained so disabled input doesn't pile up
+pub fn typed_characters(state: &KeyboardState) -> Vec<char> {
+    let mut characters = Vec::new();
+
+    while let Some(character) = get_char_pressed() {
+        characters.push(character);
+    }
+
+    // Shortcuts like ctrl+c also produce characters on some platforms
+    let shortcut = [
+        KeyCode::LeftControl,
+        KeyCode::RightControl,
+        KeyCode::LeftAlt,
+        KeyCode::LeftSuper,
+        KeyCode::RightSuper,
+    ]
+    .iter()
+    .any(|key| is_key_down(*key));
+
+    if !state.text_input || shortcut {
+        return Vec::new();
+    }
+
+    characters.retain(|character| !character.is_control());
+
+    characters
+}
+
+// Key names accepted by the keyboard module
+const KEYS: &[(&str, KeyCode)] = &[
+    ("a", KeyCode::A),
+    ("b", KeyCode::B),
+    ("c", KeyCode::C),
+    ("d", KeyCode::D),
+    ("e", KeyCode::E),
+    ("f", KeyCode::F),
+    ("g", KeyCode::G),
+    ("h", KeyCode::H),
+    ("i", KeyCode::I),
+    ("j", KeyCode::J),
+    ("k", KeyCode::K),
+    ("l", KeyCode::L),
+    ("m", KeyCode::M),
+    ("n", KeyCode::N),
+    ("o", KeyCode::O),
+    ("p", KeyCode::P),
+    ("q", KeyCode::Q),
+    ("r", KeyCode::R),
+    ("s", KeyCode::S),
+    ("t", KeyCode::T),
+    ("u", KeyCode::U),
+    ("v", KeyCode::V),
+    ("w", KeyCode::W),
+    ("x", KeyCode::X),
+    ("y", KeyCode::Y),
+    ("z", KeyCode::Z),
+    ("0", KeyCode::Key0),
+    ("1", KeyCode::Key1),
+    ("2", KeyCode::Key2),
+    ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4),
+    ("5", KeyCode::Key5),
+    ("6", KeyCode::Key6),
+    ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8),
+    ("9", KeyCode::Key9),
+    ("space", KeyCode::Space),
+    ("return", KeyCode::Enter),
+    ("escape", KeyCode::Escape),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("lshift", KeyCode::LeftShift),
+    ("rshift", KeyCode::RightShift),
+    ("lctrl", KeyCode::LeftControl),
+    ("rctrl", KeyCode::RightControl),
+    ("lalt", KeyCode::LeftAlt),
+    ("ralt", KeyCode::RightAlt),
+    ("lgui", KeyCode::LeftSuper),
+    ("rgui", KeyCode::RightSuper),
+    ("capslock", KeyCode::CapsLock),
+    (",", KeyCode::Comma),
+    (".", KeyCode::Period),
+    ("/", KeyCode::Slash),
+    (";", KeyCode::Semicolon),
+    ("'", KeyCode::Apostrophe),
+    ("-", KeyCode::Minus),
+    ("=", KeyCode::Equal),
+    ("[", KeyCode::LeftBracket),
+    ("]", KeyCode::RightBracket),
+    ("\\", KeyCode::Backslash),
+    ("`", KeyCode::GraveAccent),
+    ("f1", KeyCode::F1),
+    ("f2", KeyCode::F2),
+    ("f3", KeyCode::F3),
+    ("f4", KeyCode::F4),
+    ("f5", KeyCode::F5),
+    ("f6", KeyCode::F6),
+    ("f7", KeyCode::F7),
+    ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9),
+    ("f10", KeyCode::F10),
+    ("f11", KeyCode::F11),
+    ("f12", KeyCode::F12),
+];
+
+// Look up the KeyCode for a key name
+pub fn parse_key(name: &str) -> LuaResult<KeyCode> {
+    KEYS.iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key_code)| *key_code)
+        .ok_or_else(|| LuaError::RuntimeError(format!("Invalid key '{}'.", name)))
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, keyboard_state: &Rc<RefCell<KeyboardState>>) {
+    let keyboard_table = lua.create_table().unwrap();
+
+    let keyboard_is_down = arguments::function(lua, "pesto.keyboard.isDown", |_, key: String| {
+        Ok(is_key_down(parse_key(&key)?))
+    })
+    .unwrap();
+
+    let keyboard_is_pressed =
+        arguments::function(lua, "pesto.keyboard.isPressed", |_, key: String| {
+            Ok(is_key_pressed(parse_key(&key)?))
+        })
+        .unwrap();
+
+    let keyboard_is_released =
+        arguments::function(lua, "pesto.keyboard.isReleased", |_, key: String| {
+            Ok(is_key_released(parse_key(&key)?))
+        })
+        .unwrap();
+
+    let state = keyboard_state.clone();
+    let keyboard_set_text_input = arguments::function(
+        lua,
+        "pesto.keyboard.setTextInput",
+        move |_, enabled: bool| {
+            state.borrow_mut().text_input = enabled;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = keyboard_state.clone();
+    let keyboard_has_text_input =
+        arguments::function(lua, "pesto.keyboard.hasTextInput", move |_, ()| {
+            Ok(state.borrow().text_input)
+        })
+        .unwrap();
+
+    let state = keyboard_state.clone();
+    let keyboard_set_key_repeat = arguments::function(
+        lua,
+        "pesto.keyboard.setKeyRepeat",
+        move |_, (delay, interval): (f32, f32)| {
+            if !(delay > 0.0 && interval > 0.0) {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid key repeat delay {} and interval {}, both must be positive.",
+                    delay, interval
+                )));
+            }
+
+            let mut state = state.borrow_mut();
+
+            state.repeat_delay = delay;
+            state.repeat_interval = interval;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = keyboard_state.clone();
+    let keyboard_get_key_repeat =
+        arguments::function(lua, "pesto.keyboard.getKeyRepeat", move |_, ()| {
+            let state = state.borrow();
+
+            Ok((state.repeat_delay, state.repeat_interval))
+        })
+        .unwrap();
+
+    keyboard_table.set("isDown", keyboard_is_down).unwrap();
+    keyboard_table
+        .set("isPressed", keyboard_is_pressed)
+        .unwrap();
+    keyboard_table
+        .set("isReleased", keyboard_is_released)
+        .unwrap();
+
+    keyboard_table
+        .set("setTextInput", keyboard_set_text_input)
+        .unwrap();
+    keyboard_table
+        .set("hasTextInput", keyboard_has_text_input)
+        .unwrap();
+    keyboard_table
+        .set("setKeyRepeat", keyboard_set_key_repeat)
+        .unwrap();
+    keyboard_table
+        .set("getKeyRepeat", keyboard_get_key_repeat)
+        .unwrap();
+
+    pesto_table.set("keyboard", keyboard_table).unwrap();
+}
@@ -1,8 +1,63 @@
-use macroquad::{miniquad::conf::Icon, prelude::*};
+use archive::Archive;
+use audio::AudioState;
+use console::ConsoleState;
+use event::EventState;
+use gamepad::GamepadState;
+use graphics::GraphicsState;
+use http::HttpState;
+use ignore::IgnoreList;
+use keyboard::KeyboardState;
+use macroquad::{
+    miniquad::conf::{Icon, Platform},
+    prelude::*,
+};
+use math::MathState;
 use mlua::prelude::*;
+use mouse::MouseState;
+use notify::{EventKind, RecursiveMode, Watcher};
+use profiler::{FrameTiming, ProfilerState};
+use project::Project;
 use regex::Regex;
-use std::{env, fs, path::Path, process::Command};
+use std::{
+    cell::RefCell,
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::Command,
+    rc::Rc,
+    sync::mpsc,
+    time::Instant,
+};
+use timer::TimerState;
+use touch::TouchState;
 use walkdir::WalkDir;
+use window::WindowState;
+
+mod archive;
+mod arguments;
+mod audio;
+mod color;
+mod compression;
+mod console;
+mod encoding;
+mod event;
+mod filesystem;
+mod gamepad;
+mod graphics;
+mod hash;
+mod http;
+mod ignore;
+mod keyboard;
+mod math;
+mod mouse;
+mod noise;
+mod profiler;
+mod project;
+mod system;
+mod tilemap;
+mod timer;
+mod touch;
+mod window;
 
 // Embedded assets
 const ICON_16: &[u8; 1024] = include_bytes!("../assets/icon_16.rgba");
@@ -21,90 +76,389 @@ const LUME: &str = include_str!("../assets/lume.lua");
 const TICK: &str = include_str!("../assets/tick.lua");
 const TINY: &str = include_str!("../assets/tiny.lua");
 
-// Virtual resolution
+// Text size of the error screen
+const ERROR_FONT_SIZE: u16 = 24;
+
+// Default virtual resolution
 const VIRTUAL_WIDTH: f32 = 1280.0;
 const VIRTUAL_HEIGHT: f32 = 720.0;
 
-// Window configuration
-fn window_conf() -> Conf {
+// Project configuration read from conf.lua
+struct Config {
+    width: i32,
+    height: i32,
+    title: String,
+    identity: Option<String>,
+    vsync: bool,
+    resizable: bool,
+    virtual_width: f32,
+    virtual_height: f32,
+    fixed_timestep: Option<f32>,
+    require_paths: Vec<String>,
+    main: String,
+    icon: Option<String>,
+    msaa: i32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            width: 960,
+            height: 540,
+            title: "Pesto".to_string(),
+            identity: None,
+            vsync: true,
+            resizable: true,
+            virtual_width: VIRTUAL_WIDTH,
+            virtual_height: VIRTUAL_HEIGHT,
+            fixed_timestep: None,
+            require_paths: Vec::new(),
+            main: "main.lua".to_string(),
+            icon: None,
+            msaa: 0,
+        }
+    }
+}
+
+// The project directory is the first argument that isn't a flag or the
+// value of one
+fn project_directory(args: &[String]) -> &str {
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--headless" {
+            args.next();
+        } else if !arg.starts_with("--") {
+            return arg;
+        }
+    }
+
+    "."
+}
+
+// Flags pesto handles itself, --headless also takes the value after it
+const PESTO_FLAGS: &[&str] = &["--no-lint", "--allow-warnings", "--format", "--watch"];
+
+// Arguments forwarded to the game as pesto.arg, everything but pesto's own
+// flags and the project directory. Packaged games have no directory
+// argument so every positional is theirs
+fn game_arguments(args: &[String], packaged: bool) -> Vec<String> {
+    let mut game_args = Vec::new();
+    let mut directory_found = packaged;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--headless" {
+            args.next();
+        } else if PESTO_FLAGS.contains(&arg.as_str()) {
+            continue;
+        } else if !directory_found && !arg.starts_with("--") {
+            directory_found = true;
+        } else {
+            game_args.push(arg.clone());
+        }
+    }
+
+    game_args
+}
+
+// Read the table returned by conf.lua, missing fields keep their defaults
+fn load_config(project: &Project) -> Config {
+    let mut config = Config::default();
+
+    let Ok(conf_lua) = project.read_to_string("conf.lua") else {
+        return config;
+    };
+
+    let lua = Lua::new();
+
+    match lua.load(conf_lua).set_name("conf.lua").eval::<LuaTable>() {
+        Ok(table) => {
+            if let Ok(Some(width)) = table.get("width") {
+                config.width = width;
+            }
+
+            if let Ok(Some(height)) = table.get("height") {
+                config.height = height;
+            }
+
+            if let Ok(Some(title)) = table.get("title") {
+                config.title = title;
+            }
+
+            if let Ok(Some(identity)) = table.get("identity") {
+                config.identity = Some(identity);
+            }
+
+            if let Ok(Some(vsync)) = table.get("vsync") {
+                config.vsync = vsync;
+            }
+
+            if let Ok(Some(resizable)) = table.get("resizable") {
+                config.resizable = resizable;
+            }
+
+            // The virtual resolution becomes the size of the render target
+            if let Ok(Some(virtual_width)) = table.get::<_, Option<f32>>("virtualWidth") {
+                if virtual_width.is_finite() && virtual_width >= 1.0 {
+                    config.virtual_width = virtual_width;
+                } else {
+                    println!("Ignoring invalid virtualWidth {}.", virtual_width);
+                }
+            }
+
+            if let Ok(Some(virtual_height)) = table.get::<_, Option<f32>>("virtualHeight") {
+                if virtual_height.is_finite() && virtual_height >= 1.0 {
+                    config.virtual_height = virtual_height;
+                } else {
+                    println!("Ignoring invalid virtualHeight {}.", virtual_height);
+                }
+            }
+
+            if let Ok(Some(fixed_timestep)) = table.get::<_, Option<f32>>("fixedTimestep") {
+                if fixed_timestep.is_finite() && fixed_timestep > 0.0 {
+                    config.fixed_timestep = Some(fixed_timestep);
+                } else {
+                    println!("Ignoring invalid fixedTimestep {}.", fixed_timestep);
+                }
+            }
+
+            // The entry file, relative to the project
+            if let Ok(Some(main)) = table.get::<_, Option<String>>("main") {
+                config.main = main.trim_start_matches("./").to_string();
+            }
+
+            // Samples per pixel of the window, 0 turns multisampling off.
+            // More samples cost fill rate on every frame, and as macroquad
+            // can't multisample render targets only what is drawn straight
+            // to the window benefits, not the letterboxed game
+            if let Ok(Some(msaa)) = table.get::<_, Option<i32>>("msaa") {
+                if [0, 2, 4].contains(&msaa) {
+                    config.msaa = msaa;
+                } else {
+                    println!("Ignoring invalid msaa {}, expected 0, 2 or 4.", msaa);
+                }
+            }
+
+            // Image used as the window icon, relative to the project
+            if let Ok(Some(icon)) = table.get::<_, Option<String>>("icon") {
+                config.icon = Some(icon.trim_start_matches("./").to_string());
+            }
+
+            // Extra directories searched by require, relative to the project
+            match table.get::<_, Option<Vec<String>>>("requirePaths") {
+                Ok(Some(require_paths)) => {
+                    config.require_paths = require_paths
+                        .iter()
+                        .map(|path| path.trim_matches('/').to_string())
+                        .collect();
+                }
+                Ok(None) => {}
+                Err(_err) => println!("Ignoring requirePaths, expected a list of directories."),
+            }
+        }
+        Err(err) => println!("{}", err),
+    }
+
+    config
+}
+
+// Decode the project's icon and scale it to every size the window needs
+fn load_icon(project: &Project, path: &str) -> Result<Icon, String> {
+    let bytes = project.read(path).map_err(|err| err.to_string())?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|err| err.to_string())?
+        .to_rgba8();
+
+    let scaled = |size: u32| {
+        image::imageops::resize(&image, size, size, image::imageops::FilterType::Triangle)
+            .into_raw()
+    };
+
+    Ok(Icon {
+        small: scaled(16).try_into().unwrap(),
+        medium: scaled(32).try_into().unwrap(),
+        big: scaled(64).try_into().unwrap(),
+    })
+}
+
+// Window configuration, projects without a usable icon get pesto's
+fn window_conf(config: &Config, project: &Project) -> Conf {
+    let icon = config.icon.as_ref().and_then(|path| {
+        load_icon(project, path)
+            .map_err(|err| println!("Could not load icon '{}': {}", path, err))
+            .ok()
+    });
+
     Conf {
-        window_title: "Pesto".to_owned(),
-        window_width: 960,
-        window_height: 540,
-        icon: Option::Some(Icon {
+        window_title: config.title.clone(),
+        window_width: config.width,
+        window_height: config.height,
+        window_resizable: config.resizable,
+        sample_count: config.msaa.max(1),
+        icon: Some(icon.unwrap_or(Icon {
             small: ICON_16.to_owned(),
             medium: ICON_32.to_owned(),
             big: ICON_64.to_owned(),
-        }),
+        })),
+        platform: Platform {
+            swap_interval: if config.vsync { None } else { Some(0) },
+            ..Default::default()
+        },
         ..Default::default()
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut error = false;
-    let mut error_message: String = "".to_string();
+// Extract an embedded windows tool, elsewhere look for it on the PATH
+fn find_tool(name: &str, embedded: &[u8]) -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        let path = env::temp_dir().join(format!("{}.exe", name));
 
-    let directory;
+        if !path.exists() {
+            fs::write(&path, embedded).unwrap();
+        }
 
-    // Handle command line arguments
-    let args: Vec<String> = env::args().collect();
+        return Some(path);
+    }
 
-    if args.len() != 2 {
-        directory = ".";
+    if Command::new(name).arg("--version").output().is_ok() {
+        Some(PathBuf::from(name))
     } else {
-        directory = &args[1];
+        println!("{} not found on the PATH, skipping.", name);
+        None
     }
+}
 
-    // Extract luacheck and lua-format if not present
-    let luacheck_path = env::temp_dir().join("luacheck.exe");
-    let luaformat_path = env::temp_dir().join("lua-format.exe");
+// Create the render target and camera games are drawn into
+fn letterbox_target(size: Vec2) -> (RenderTarget, Camera2D) {
+    let render_target = render_target(size.x as u32, size.y as u32);
+    render_target.texture.set_filter(FilterMode::Nearest);
 
-    if !luacheck_path.exists() {
-        fs::write(&luacheck_path, LUACHECK).unwrap();
-    }
+    let render_target_cam = graphics::letterbox_camera(&render_target);
+
+    (render_target, render_target_cam)
+}
 
-    if !luaformat_path.exists() {
-        fs::write(&luaformat_path, LUAFORMAT).unwrap();
+// Rust side state shared between the main loop and the lua api
+#[derive(Clone)]
+struct State {
+    graphics: Rc<RefCell<GraphicsState>>,
+    keyboard: Rc<RefCell<KeyboardState>>,
+    mouse: Rc<RefCell<MouseState>>,
+    touch: Rc<RefCell<TouchState>>,
+    window: Rc<RefCell<WindowState>>,
+    audio: Rc<RefCell<AudioState>>,
+    console: Rc<RefCell<ConsoleState>>,
+    math: Rc<RefCell<MathState>>,
+    event: Rc<RefCell<EventState>>,
+    timer: Rc<RefCell<TimerState>>,
+    http: Rc<RefCell<HttpState>>,
+    gamepad: Rc<RefCell<GamepadState>>,
+    save_directory: PathBuf,
+    require_paths: Vec<String>,
+    main: String,
+    arguments: Vec<String>,
+    project: Rc<Project>,
+}
+
+impl State {
+    // Directories require searches, relative to the project root
+    fn require_roots(&self) -> Vec<String> {
+        let mut roots = vec![String::new()];
+        roots.extend(self.require_paths.iter().cloned());
+
+        roots
     }
+}
 
-    // Load lua
-    let lua = Lua::new();
+fn new_state(config: &Config, project: Project, args: &[String]) -> State {
+    State {
+        graphics: Rc::new(RefCell::new(GraphicsState::new(vec2(
+            config.virtual_width,
+            config.virtual_height,
+        )))),
+        keyboard: Rc::new(RefCell::new(KeyboardState::default())),
+        mouse: Rc::new(RefCell::new(MouseState::default())),
+        touch: Rc::new(RefCell::new(TouchState::default())),
+        window: Rc::new(RefCell::new(WindowState {
+            title: config.title.clone(),
+            fullscreen: false,
+        })),
+        audio: Rc::new(RefCell::new(AudioState::default())),
+        console: Rc::new(RefCell::new(ConsoleState::default())),
+        math: Rc::new(RefCell::new(MathState::default())),
+        event: Rc::new(RefCell::new(EventState::default())),
+        timer: Rc::new(RefCell::new(TimerState::new(config.fixed_timestep))),
+        http: Rc::new(RefCell::new(HttpState::default())),
+        gamepad: Rc::new(RefCell::new(GamepadState::new())),
+        // Saves are separated by identity, falling back to the title
+        save_directory: filesystem::save_directory(
+            config.identity.as_deref().unwrap_or(&config.title),
+        ),
+        require_paths: config.require_paths.clone(),
+        main: config.main.clone(),
+        arguments: game_arguments(args, project.packaged),
+        project: Rc::new(project),
+    }
+}
 
+// Load the pesto api and external libraries into a fresh lua state
+fn load_api(lua: &Lua, state: &State) {
     let globals = lua.globals();
 
-    // Setup require search path
-    let package_path = env::current_dir().unwrap().join(directory).join("?.lua");
+    // Setup require search path, the project root and every directory in
+    // requirePaths are searched before the default path for both module.lua
+    // and module/init.lua
+    let project_directory = env::current_dir().unwrap().join(&state.project.directory);
 
-    let package_table: LuaTable = globals.get("package").unwrap();
+    let mut package_path: Vec<String> = state
+        .require_roots()
+        .iter()
+        .flat_map(|root| {
+            let root = project_directory.join(root);
 
-    package_table
-        .set(
-            "path",
-            format!(
-                "{}{}",
-                package_table.get::<_, String>("path").unwrap(),
-                package_path.to_str().unwrap().to_string()
-            ),
-        )
-        .unwrap();
+            [
+                root.join("?.lua").to_string_lossy().to_string(),
+                root.join("?")
+                    .join("init.lua")
+                    .to_string_lossy()
+                    .to_string(),
+            ]
+        })
+        .collect();
 
-    // Load api
-    let pesto_table = lua.create_table().unwrap();
+    let package_table: LuaTable = globals.get("package").unwrap();
 
-    let graphics_table = lua.create_table().unwrap();
+    package_path.push(package_table.get::<_, String>("path").unwrap());
 
-    let graphics_circle = lua
-        .create_function(|_, (x, y, radius): (f32, f32, f32)| {
-            draw_circle(x, y, radius, WHITE);
+    package_table.set("path", package_path.join(";")).unwrap();
 
-            Ok(())
-        })
-        .unwrap();
+    project::add_archive_loader(lua, &state.project, &state.require_roots());
 
-    graphics_table.set("circle", graphics_circle).unwrap();
+    // Load api
+    let pesto_table = lua.create_table().unwrap();
 
-    pesto_table.set("graphics", graphics_table).unwrap();
+    graphics::register(
+        lua,
+        &pesto_table,
+        &state.project,
+        &state.save_directory,
+        &state.graphics,
+    );
+    keyboard::register(lua, &pesto_table, &state.keyboard);
+    mouse::register(lua, &pesto_table, &state.mouse);
+    touch::register(lua, &pesto_table, &state.touch);
+    gamepad::register(lua, &pesto_table, &state.gamepad);
+    audio::register(lua, &pesto_table, &state.project, &state.audio);
+    window::register(lua, &pesto_table, &state.window);
+    event::register(lua, &pesto_table, &state.event);
+    filesystem::register(lua, &pesto_table, &state.project, &state.save_directory);
+    math::register(lua, &pesto_table, &state.math);
+    tilemap::register(lua, &pesto_table, &state.project);
+    color::register(lua, &pesto_table);
+    system::register(lua, &pesto_table);
+    http::register(lua, &pesto_table, &state.http);
+    console::register(lua, &state.console);
 
     // Load external libraries
     let bump = lua.load(BUMP).eval::<LuaTable>().unwrap();
@@ -116,149 +470,714 @@ async fn main() {
     let tick = lua.load(TICK).eval::<LuaTable>().unwrap();
     let tiny = lua.load(TINY).eval::<LuaTable>().unwrap();
 
+    // bump is mounted as is, pesto.collision.newWorld(cellSize) is already
+    // its entry point and the world has add, move, check and query methods
     pesto_table.set("collision", bump).unwrap();
     pesto_table.set("Object", classic).unwrap();
     pesto_table.set("tween", flux).unwrap();
     pesto_table.set("inspect", inspect).unwrap();
     pesto_table.set("json", json).unwrap();
     pesto_table.set("utils", lume).unwrap();
+    hash::register(lua, &pesto_table, &state.project, &state.save_directory);
+    compression::register(lua, &pesto_table);
+    encoding::register(lua, &pesto_table);
     pesto_table.set("timer", tick).unwrap();
     pesto_table.set("ecs", tiny).unwrap();
 
-    lua.globals().set("pesto", pesto_table).unwrap();
+    timer::register(lua, &pesto_table, &state.timer);
 
-    // Check if main.lua exists in the given directory
-    let main_lua_path = Path::new(directory).join("main.lua");
+    pesto_table
+        .set(
+            "arg",
+            lua.create_sequence_from(state.arguments.clone()).unwrap(),
+        )
+        .unwrap();
 
-    if !main_lua_path.exists() {
-        error = true;
-        error_message = "main.lua not found.".to_string()
+    globals.set("pesto", pesto_table).unwrap();
+}
+
+// Located luacheck and whether warnings alone stop the game from running
+struct Luacheck {
+    path: PathBuf,
+    allow_warnings: bool,
+}
+
+// Packaged games have nothing to lint
+fn find_luacheck(args: &[String], packaged: bool) -> Option<Luacheck> {
+    if packaged || args.iter().any(|arg| arg == "--no-lint") {
+        return None;
     }
 
-    if !error {
-        // Lint all lua files
-        let output = Command::new(luacheck_path)
-            .arg(directory)
-            .arg("--globals")
-            .arg("pesto")
-            .output()
-            .unwrap();
+    Some(Luacheck {
+        path: find_tool("luacheck", LUACHECK)?,
+        allow_warnings: args.iter().any(|arg| arg == "--allow-warnings"),
+    })
+}
+
+// Lint all lua files not matched by .pestoignore, returning the luacheck
+// report if it found problems that should stop the game from running
+fn lint_project(directory: &str, luacheck: &Luacheck) -> Option<String> {
+    let mut command = Command::new(&luacheck.path);
+    command.arg(directory).arg("--globals").arg("pesto");
+
+    let ignored = IgnoreList::load(directory).globs(directory);
+
+    if !ignored.is_empty() {
+        command.arg("--exclude-files").args(ignored);
+    }
+
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => {
+            println!("Could not run luacheck, skipping: {}", err);
+            return None;
+        }
+    };
+
+    let report = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // The summary reads like "Total: 1 warning / 0 errors in 2 files"
+    let regex = Regex::new(r"(\d+)\s+warnings?\s*/\s*(\d+)\s+errors?").unwrap();
 
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let Some(captures) = regex.captures(&report) else {
+        // Without a summary only the exit code tells whether luacheck
+        // found anything, so the whole output is shown either way
+        println!("Unexpected luacheck output:\n{}", report);
 
-        let regex = Regex::new(r"(\d+) (warning|warnings) / (\d+) (error|errors)").unwrap();
+        return (!output.status.success()).then_some(report);
+    };
 
-        if let Some(captures) = regex.captures(&stdout) {
-            let warnings = captures[1].parse::<u32>().unwrap();
-            let errors = captures[3].parse::<u32>().unwrap();
+    let warnings = captures[1].parse::<u32>().unwrap_or(0);
+    let errors = captures[2].parse::<u32>().unwrap_or(0);
 
-            if errors > 0 || warnings > 0 {
-                error = true;
-                error_message = stdout;
+    if errors > 0 || (warnings > 0 && !luacheck.allow_warnings) {
+        return Some(report);
+    }
+
+    if warnings > 0 {
+        println!("{}", report);
+        println!("Running with {} luacheck warnings.", warnings);
+    }
+
+    None
+}
+
+// Format all lua files in place except the ones matched by .pestoignore,
+// reporting the ones that actually changed
+fn format_project(directory: &str, luaformat_path: &Path) {
+    let ignore_list = IgnoreList::load(directory);
+
+    for entry in WalkDir::new(directory).into_iter().flatten() {
+        let path = entry.path();
+
+        let relative = path.strip_prefix(directory).unwrap_or(path);
+
+        if ignore_list.is_ignored(relative) {
+            continue;
+        }
+
+        // Other files, including ones without an extension, are left alone
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "lua") {
+            let before = fs::read(path).ok();
+
+            Command::new(luaformat_path)
+                .arg(path)
+                .arg("-i")
+                .status()
+                .unwrap();
+
+            if fs::read(path).ok() != before {
+                println!("Formatted {}", path.display());
             }
         }
+    }
+}
 
-        // Format all lua files
-        for entry in WalkDir::new(directory) {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-
-                if path.is_file() && path.extension().unwrap().to_str() == Some("lua") {
-                    Command::new(luaformat_path.clone())
-                        .arg(path)
-                        .arg("-i")
-                        .status()
-                        .unwrap();
-                }
+// Run lua code, turning errors and panics inside the api it calls into a
+// message for the error screen instead of aborting
+fn protect<T>(f: impl FnOnce() -> LuaResult<T>) -> Result<T, String> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown error".to_string());
+
+            Err(format!("Panic: {}", message))
+        }
+    }
+}
+
+// Lint and execute the entry file, main.lua unless conf.lua sets main, then
+// call load once. The window and graphics context already exist at this
+// point so loading textures in load is safe
+fn load_project(
+    lua: &Lua,
+    directory: &str,
+    luacheck: Option<&Luacheck>,
+    state: &State,
+) -> Result<(), String> {
+    // A mistyped directory would otherwise only show up as a missing entry
+    // file
+    if state.project.archive.is_none() && !state.project.directory.is_dir() {
+        return Err(format!("Project directory '{}' not found.", directory));
+    }
+
+    load_api(lua, state);
+
+    // Check if the entry file exists in the given directory or archive
+    let Ok(main_lua) = state.project.read_to_string(&state.main) else {
+        return Err(format!("{} not found.", state.main));
+    };
+
+    if let Some(luacheck) = luacheck {
+        if let Some(report) = lint_project(directory, luacheck) {
+            return Err(report);
+        }
+    }
+
+    // Execute the entry file
+    protect(|| lua.load(main_lua).set_name(&state.main).exec())?;
+
+    let pesto_table: LuaTable = lua.globals().get("pesto").unwrap();
+
+    if let Ok(load_function) = pesto_table.get::<_, LuaFunction>("load") {
+        protect(|| load_function.call::<_, ()>(()))?;
+    }
+
+    Ok(())
+}
+
+// Find where a lua error happened, chunks loaded by pesto are named after
+// their file while required modules carry their full path
+fn error_location(message: &str) -> Option<(String, usize)> {
+    let regex =
+        Regex::new(r#"(?:\[string "([^"]+)"\]|((?:[A-Za-z]:)?[^\s:"]+\.lua)):(\d+):"#).unwrap();
+
+    let captures = regex.captures(message)?;
+    let file = captures.get(1).or(captures.get(2))?.as_str().to_string();
+    let line = captures[3].parse().ok()?;
+
+    Some((file, line))
+}
+
+// Lines shown on the error screen, wrapped to the given width with the
+// offending source line after the error itself
+fn error_lines(project: &Project, message: &str, width: f32) -> Vec<String> {
+    let message = message.replace('\t', "    ");
+    let (first_line, rest) = message.split_once('\n').unwrap_or((&message, ""));
+
+    let mut text = first_line.to_string();
+
+    if let Some((file, line)) = error_location(first_line) {
+        let source = project.read_to_string(&file).ok().and_then(|source| {
+            source
+                .lines()
+                .nth(line.checked_sub(1)?)
+                .map(|line| line.trim().to_string())
+        });
+
+        if let Some(source) = source {
+            text.push_str(&format!("\n\n{}:{}\n>> {}", file, line, source));
+        }
+    }
+
+    if !rest.is_empty() {
+        text.push_str(&format!("\n\n{}", rest));
+    }
+
+    graphics::wrap_text(&text, width, None, ERROR_FONT_SIZE)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // pesto package <directory> [output] bundles a project into a single
+    // executable instead of running it
+    if args.first().is_some_and(|arg| arg == "package") {
+        let directory = args.get(1).map_or(".", |arg| arg.as_str());
+
+        let output = match args.get(2) {
+            Some(output) => PathBuf::from(output),
+            None => {
+                let name = fs::canonicalize(directory)
+                    .ok()
+                    .and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+                    .unwrap_or_else(|| "game".to_string());
+
+                PathBuf::from(format!("{}{}", name, env::consts::EXE_SUFFIX))
+            }
+        };
+
+        let config = load_config(&Project {
+            directory: PathBuf::from(directory),
+            archive: None,
+            packaged: false,
+        });
+
+        match archive::package(directory, &config.main, &output) {
+            Ok(()) => println!("Packaged '{}' into '{}'.", directory, output.display()),
+            Err(err) => {
+                println!("Could not package '{}': {}", directory, err);
+                std::process::exit(1);
             }
         }
+
+        return;
     }
 
-    if !error {
-        // Execute main.lua
-        let main_lua = fs::read_to_string(main_lua_path).unwrap();
+    // Packaged games run the appended project, a zip can also be run in
+    // place of a directory, otherwise files are read from disk
+    let directory = project_directory(&args);
 
-        if let Err(err) = lua.load(main_lua).set_name("main.lua").exec() {
-            error = true;
-            error_message = err.to_string()
+    let executable_archive = Archive::from_executable();
+    let packaged = executable_archive.is_some();
+
+    let archive = executable_archive.or_else(|| {
+        if !directory.ends_with(".zip") {
+            return None;
+        }
+
+        let archive = fs::read(directory)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| Archive::from_zip(&bytes));
+
+        match archive {
+            Ok(archive) => Some(archive),
+            Err(err) => {
+                println!("Could not read '{}': {}", directory, err);
+                std::process::exit(1);
+            }
         }
+    });
+
+    let project = Project {
+        directory: PathBuf::from(directory),
+        archive,
+        packaged,
+    };
+
+    let config = load_config(&project);
+
+    // pesto --headless N runs load and N updates without opening a window
+    if let Some(position) = args.iter().position(|arg| arg == "--headless") {
+        let ticks = match args.get(position + 1).map(|ticks| ticks.parse::<u32>()) {
+            Some(Ok(ticks)) => ticks,
+            _ => {
+                println!("--headless needs the number of updates to run.");
+                std::process::exit(1);
+            }
+        };
+
+        std::process::exit(run_headless(&args, config, project, ticks));
     }
 
+    macroquad::Window::from_config(window_conf(&config, &project), run(args, config, project));
+}
+
+// Headless runs call update with a constant dt, the fixed timestep if the
+// project sets one. Without a window there is no graphics context or input,
+// so only game logic can run and api calls that need them raise an error
+fn run_headless(args: &[String], config: Config, project: Project, ticks: u32) -> i32 {
+    let luacheck = find_luacheck(args, project.archive.is_some());
+
+    let directory = project_directory(args);
+    let dt = config.fixed_timestep.unwrap_or(1.0 / 60.0);
+
+    let state = new_state(&config, project, args);
+    let lua = Lua::new();
+
+    let result = load_project(&lua, directory, luacheck.as_ref(), &state).and_then(|()| {
+        let pesto_table: LuaTable = lua.globals().get("pesto").unwrap();
+
+        let Ok(update_function) = pesto_table.get::<_, LuaFunction>("update") else {
+            return Err("Update function not found.".to_string());
+        };
+
+        for _ in 0..ticks {
+            protect(|| http::poll(&lua, &state.http))?;
+            protect(|| update_function.call::<_, ()>(dt))?;
+
+            if state.event.borrow().quit.is_some() {
+                break;
+            }
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => state.event.borrow().quit.unwrap_or(0),
+        Err(message) => {
+            println!("{}", message);
+            1
+        }
+    }
+}
+
+async fn run(args: Vec<String>, config: Config, project: Project) {
+    let mut error = false;
+    let mut error_message: String = "".to_string();
+
+    // Handle command line arguments, packaged games have nothing to lint,
+    // format or watch
+    let packaged = project.archive.is_some();
+
+    let format = !packaged && args.iter().any(|arg| arg == "--format");
+    let watch = !packaged && args.iter().any(|arg| arg == "--watch");
+
+    let directory = project_directory(&args);
+
+    // Locate luacheck and lua-format
+    let luacheck = find_luacheck(&args, packaged);
+
+    let luaformat_path = if format {
+        find_tool("lua-format", LUAFORMAT)
+    } else {
+        None
+    };
+
+    let virtual_resolution = vec2(config.virtual_width, config.virtual_height);
+
+    let state = new_state(&config, project, &args);
+    let max_texture_size = graphics::max_texture_size();
+    state.graphics.borrow_mut().max_texture_size = Some(max_texture_size);
+
+    // Formatting rewrites files so it only happens with --format, and only on
+    // startup so saving while watching doesn't fight with the formatter
+    if let Some(luaformat_path) = &luaformat_path {
+        format_project(directory, luaformat_path);
+    }
+
+    let mut lua = Lua::new();
+
+    if let Err(message) = load_project(&lua, directory, luacheck.as_ref(), &state) {
+        error = true;
+        error_message = message;
+    }
+
+    // Watch the project directory for changes to lua files
+    let (watch_sender, watch_receiver) = mpsc::channel();
+
+    let _watcher = if watch && Path::new(directory).is_dir() {
+        let mut watcher = notify::recommended_watcher(watch_sender).unwrap();
+
+        watcher
+            .watch(Path::new(directory), RecursiveMode::Recursive)
+            .unwrap();
+
+        Some(watcher)
+    } else {
+        None
+    };
+
     // Macroquad letterbox setup
-    let render_target = render_target(VIRTUAL_WIDTH as u32, VIRTUAL_HEIGHT as u32);
-    render_target.texture.set_filter(FilterMode::Nearest);
+    let mut virtual_size = state.graphics.borrow().virtual_resolution;
+
+    let (mut render_target, mut render_target_cam) = letterbox_target(virtual_size);
+
+    let mut window_size = vec2(screen_width(), screen_height());
+
+    let mut error_lines_message = String::new();
+    let mut displayed_error_lines = Vec::new();
 
-    let mut render_target_cam =
-        Camera2D::from_display_rect(Rect::new(0., 0., VIRTUAL_WIDTH, VIRTUAL_HEIGHT));
-    render_target_cam.render_target = Some(render_target.clone());
+    // Like the console the profiler keeps its history across reloads
+    let mut profiler = ProfilerState::default();
 
-    // Main loop
-    loop {
-        // Letterbox update
-        let scale: f32 = f32::min(
-            screen_width() / VIRTUAL_WIDTH,
-            screen_height() / VIRTUAL_HEIGHT,
+    // Main loop, runs until the game quits
+    let exit_code = loop {
+        let mut timing = FrameTiming::default();
+        graphics::reset_stats(&state.graphics.borrow());
+
+        // Reload the project when a lua file changed, errors are shown on
+        // the error screen and the next good save recovers
+        let changed = watch_receiver
+            .try_iter()
+            .flatten()
+            .filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+            .any(|event| {
+                event
+                    .paths
+                    .iter()
+                    .any(|path| path.extension().is_some_and(|extension| extension == "lua"))
+            });
+
+        let restart = std::mem::take(&mut state.event.borrow_mut().restart);
+
+        // The error screen can also be dismissed by reloading with R
+        if changed || restart || (error && is_key_pressed(KeyCode::R)) {
+            *state.graphics.borrow_mut() = GraphicsState::new(virtual_resolution);
+            state.graphics.borrow_mut().max_texture_size = Some(max_texture_size);
+            *state.event.borrow_mut() = EventState::default();
+            state.audio.borrow_mut().stop_all();
+            *state.audio.borrow_mut() = AudioState::default();
+            *state.timer.borrow_mut() = TimerState::new(config.fixed_timestep);
+            *state.http.borrow_mut() = HttpState::default();
+
+            lua = Lua::new();
+
+            match load_project(&lua, directory, luacheck.as_ref(), &state) {
+                Ok(()) => error = false,
+                Err(message) => {
+                    error = true;
+                    error_message = message;
+                }
+            }
+        }
+
+        // Recreate the render target if the virtual resolution changed
+        if state.graphics.borrow().virtual_resolution != virtual_size {
+            virtual_size = state.graphics.borrow().virtual_resolution;
+
+            (render_target, render_target_cam) = letterbox_target(virtual_size);
+        }
+
+        // Compare against the previous frame so pesto.resize only fires on
+        // actual changes
+        let resized = vec2(screen_width(), screen_height()) != window_size;
+        window_size = vec2(screen_width(), screen_height());
+
+        // Letterbox update, pixel perfect scaling only uses whole scales
+        // unless the window is smaller than the virtual resolution
+        let mut scale: f32 = f32::min(
+            screen_width() / virtual_size.x,
+            screen_height() / virtual_size.y,
         );
 
-        let _virtual_mouse_pos = Vec2 {
-            x: (mouse_position().0 - (screen_width() - (VIRTUAL_WIDTH * scale)) * 0.5) / scale,
-            y: (mouse_position().1 - (screen_height() - (VIRTUAL_HEIGHT * scale)) * 0.5) / scale,
-        };
+        let mut offset = (vec2(screen_width(), screen_height()) - virtual_size * scale) * 0.5;
+
+        // Whole pixel offsets too, so virtual pixels line up with the screen
+        if state.graphics.borrow().pixel_perfect && scale >= 1.0 {
+            scale = scale.floor();
+            offset = ((vec2(screen_width(), screen_height()) - virtual_size * scale) * 0.5).floor();
+        }
+
+        state
+            .mouse
+            .borrow_mut()
+            .update(Vec2::from(mouse_position()), offset, scale);
+
+        state.touch.borrow_mut().update(offset, scale);
 
+        state.gamepad.borrow_mut().update();
+
+        state.graphics.borrow_mut().screen_target = Some(render_target.clone());
         set_camera(&render_target_cam);
 
         if error {
+            state.mouse.borrow_mut().release();
+
             clear_background(SKYBLUE);
 
             draw_text("ERROR", 10.0, 50.0, 80.0, WHITE);
+            draw_text("Press R to reload", 260.0, 50.0, 32.0, WHITE);
+
+            // Only wrap again when the message changes, lines that don't
+            // fit on the screen are cut off
+            if error_lines_message != error_message {
+                error_lines_message = error_message.clone();
+                displayed_error_lines =
+                    error_lines(&state.project, &error_message, virtual_size.x - 20.0);
+            }
+
+            let line_height = ERROR_FONT_SIZE as f32 * 1.25;
 
-            let lines: Vec<&str> = error_message.lines().collect();
-            let line_height = 50.0;
+            for (i, line) in displayed_error_lines.iter().enumerate() {
+                let y = 100.0 + i as f32 * line_height;
 
-            for (i, line) in lines.iter().enumerate() {
-                let y = i as f32 * line_height;
-                draw_text(line, 10.0, 100.0 + y, 32.0, WHITE);
+                if y > virtual_size.y {
+                    break;
+                }
+
+                draw_text(line, 10.0, y, ERROR_FONT_SIZE as f32, WHITE);
             }
         } else {
-            clear_background(BLACK);
+            clear_background(state.graphics.borrow().background_color);
 
             let pesto_table: LuaTable = lua.globals().get("pesto").unwrap();
 
-            match pesto_table.get::<_, LuaFunction>("update") {
-                Ok(update_function) => {
-                    if let Err(err) = update_function.call::<_, ()>(get_frame_time()) {
+            if resized {
+                if let Ok(resize_function) = pesto_table.get::<_, LuaFunction>("resize") {
+                    if let Err(message) = protect(|| {
+                        resize_function.call::<_, ()>((window_size.x as i32, window_size.y as i32))
+                    }) {
                         error = true;
-                        error_message = err.to_string()
+                        error_message = message;
                     }
                 }
-                Err(_err) => {
+            }
+
+            // Everything before draw counts as update time
+            let update_start = Instant::now();
+
+            // Finished http requests call back before anything else
+            if !error {
+                if let Err(message) = protect(|| http::poll(&lua, &state.http)) {
+                    error = true;
+                    error_message = message;
+                }
+            }
+
+            // While images are loading in the background a game with a
+            // loading callback gets that instead of update and draw
+            let loading = graphics::poll_images(&mut state.graphics.borrow_mut())
+                .zip(pesto_table.get::<_, LuaFunction>("loading").ok());
+
+            if let Some((progress, loading_function)) = &loading {
+                if let Err(message) = protect(|| loading_function.call::<_, ()>(*progress)) {
                     error = true;
-                    error_message = "Update function not found.".to_string();
+                    error_message = message;
+                }
+            }
+
+            let running = loading.is_none();
+
+            // Key presses and their repeats come first, like in love
+            let keys = keyboard::pressed_keys(&mut state.keyboard.borrow_mut(), get_frame_time());
+
+            if !error && running {
+                if let Ok(keypressed_function) = pesto_table.get::<_, LuaFunction>("keypressed") {
+                    for (key, repeat) in keys {
+                        if let Err(message) =
+                            protect(|| keypressed_function.call::<_, ()>((key, repeat)))
+                        {
+                            error = true;
+                            error_message = message;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // Typed characters are sent one at a time before update
+            let characters = keyboard::typed_characters(&state.keyboard.borrow());
+
+            if !error && running {
+                if let Ok(textinput_function) = pesto_table.get::<_, LuaFunction>("textinput") {
+                    for character in characters {
+                        if let Err(message) =
+                            protect(|| textinput_function.call::<_, ()>(character.to_string()))
+                        {
+                            error = true;
+                            error_message = message;
+                            break;
+                        }
+                    }
                 }
-            };
+            }
+
+            // Touches are sent in id order before update too
+            let touches = touch::touch_events(&state.touch.borrow());
+
+            if !error && running {
+                for (callback, id, x, y) in touches {
+                    if let Ok(touch_function) = pesto_table.get::<_, LuaFunction>(callback) {
+                        if let Err(message) = protect(|| touch_function.call::<_, ()>((id, x, y))) {
+                            error = true;
+                            error_message = message;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // Update keeps running when the window loses focus and there is
+            // no pesto.focus callback. macroquad 0.4 handles miniquad's
+            // focus, minimize and restore events itself and never passes
+            // them on, so pausing on focus loss is not supported for now
+            //
+            // With a fixed timestep update runs as many times as the
+            // accumulated time allows, possibly zero
+            if !error && running {
+                match pesto_table.get::<_, LuaFunction>("update") {
+                    Ok(update_function) => {
+                        let (steps, dt) = state.timer.borrow_mut().steps(get_frame_time());
+
+                        for _ in 0..steps {
+                            if let Err(message) = protect(|| update_function.call::<_, ()>(dt)) {
+                                error = true;
+                                error_message = message;
+                                break;
+                            }
+                        }
+                    }
+                    Err(_err) => {
+                        error = true;
+                        error_message = "Update function not found.".to_string();
+                    }
+                };
+            }
+
+            timing.update = update_start.elapsed();
+
+            // Draw is optional, projects without it can still draw from update.
+            // It gets how far along the frame is towards the next fixed update
+            let draw_start = Instant::now();
+
+            if !error && running {
+                if let Ok(draw_function) = pesto_table.get::<_, LuaFunction>("draw") {
+                    let alpha = state.timer.borrow().alpha();
+
+                    if let Err(message) = protect(|| draw_function.call::<_, ()>(alpha)) {
+                        error = true;
+                        error_message = message;
+                    }
+                }
+            }
+
+            timing.draw = draw_start.elapsed();
         }
 
+        let present_start = Instant::now();
+
+        // Transforms and the scissor only last for the frame they were set in
+        graphics::reset_frame(&mut state.graphics.borrow_mut());
+
+        // The console is drawn untransformed on top of everything, even if
+        // the game left a canvas active
+        set_camera(&render_target_cam);
+        console::update_and_draw(&mut state.console.borrow_mut(), virtual_size);
+        profiler::update_and_draw(&mut profiler, virtual_size);
+
         // Draw letterboxed render texture
         set_default_camera();
 
         if error {
             clear_background(SKYBLUE);
         } else {
-            clear_background(LIME);
+            clear_background(state.graphics.borrow().letterbox_color);
         }
 
         draw_texture_ex(
             &render_target.texture,
-            (screen_width() - (VIRTUAL_WIDTH * scale)) * 0.5,
-            (screen_height() - (VIRTUAL_HEIGHT * scale)) * 0.5,
+            offset.x,
+            offset.y,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(vec2(VIRTUAL_WIDTH * scale, VIRTUAL_HEIGHT * scale)),
+                dest_size: Some(vec2(virtual_size.x * scale, virtual_size.y * scale)),
                 flip_y: true,
                 ..Default::default()
             },
         );
 
         next_frame().await;
+
+        timing.present = present_start.elapsed();
+        profiler.record(timing);
+
+        // Quitting waits for the frame to finish instead of exiting mid draw
+        if let Some(code) = state.event.borrow().quit {
+            break code;
+        }
+    };
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
     }
 }
@@ -0,0 +1,250 @@
+use crate::{arguments, noise::Noise};
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// Rust side math state, kept across reloads like the random generator
+pub struct MathState {
+    pub random: Random,
+    pub noise: Noise,
+}
+
+impl Default for MathState {
+    fn default() -> Self {
+        MathState {
+            random: Random { state: 0 },
+            noise: Noise::new(0),
+        }
+    }
+}
+
+// The same PCG generator macroquad uses, kept here so its state can be
+// saved and restored. Seeds give the same sequences as macroquad's srand
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        let mut random = Random { state: 0 };
+        random.next();
+        random.state = random.state.wrapping_add(seed);
+        random.next();
+
+        random
+    }
+
+    pub fn next(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // Dividing by 2^32 in f64 keeps all 32 bits and never reaches max
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * (self.next() as f64 / 4294967296.0)
+    }
+}
+
+// Largest integer a double holds exactly, 2^53
+const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+// Random number in [min, max], integers when both bounds are whole numbers
+// and floats otherwise. min greater than max is an error
+fn random_range(random: &mut Random, min: f64, max: f64) -> LuaResult<LuaValue<'static>> {
+    if min > max {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid random range, min ({}) is greater than max ({}).",
+            min, max
+        )));
+    }
+
+    if min.fract() == 0.0 && max.fract() == 0.0 {
+        // Past 2^53 doubles can't hold every integer, so the range would
+        // silently skip values
+        if min < -MAX_EXACT_INTEGER || max > MAX_EXACT_INTEGER {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid random range {} to {}, expected integers between -2^53 and 2^53.",
+                min, max
+            )));
+        }
+
+        // gen_range truncates towards zero, so scale the raw value instead
+        // to keep every integer in the range equally likely. Ranges wider
+        // than 32 bits take two values so none of the integers are skipped
+        let span = (max - min) as u64 + 1;
+        let offset = if span <= 1 << 32 {
+            (random.next() as u64 * span) >> 32
+        } else {
+            let raw = ((random.next() as u64) << 32) | random.next() as u64;
+
+            ((raw as u128 * span as u128) >> 64) as u64
+        };
+
+        Ok(LuaValue::Integer(min as i64 + offset as i64))
+    } else {
+        Ok(LuaValue::Number(random.range(min, max)))
+    }
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, math_state: &Rc<RefCell<MathState>>) {
+    let math_table = lua.create_table().unwrap();
+
+    // random() is a float in [0, 1), random(max) is in [1, max] and
+    // random(min, max) is in [min, max]
+    let state = math_state.clone();
+    let math_random = arguments::function(
+        lua,
+        "pesto.math.random",
+        move |_, (a, b): (Option<f64>, Option<f64>)| {
+            let random = &mut state.borrow_mut().random;
+
+            match (a, b) {
+                (Some(min), Some(max)) => random_range(random, min, max),
+                (Some(max), None) => random_range(random, 1.0, max),
+                _ => Ok(LuaValue::Number(random.range(0.0, 1.0))),
+            }
+        },
+    )
+    .unwrap();
+
+    // The seed is shared by random and noise
+    let state = math_state.clone();
+    let math_set_random_seed =
+        arguments::function(lua, "pesto.math.setRandomSeed", move |_, seed: i64| {
+            let mut state = state.borrow_mut();
+            state.random = Random::new(seed as u64);
+            state.noise = Noise::new(seed as u64);
+
+            Ok(())
+        })
+        .unwrap();
+
+    // The state is the generator's 64 bit state as 16 lowercase hex digits,
+    // so it survives being written to a file. Noise isn't part of it
+    let state = math_state.clone();
+    let math_get_random_state =
+        arguments::function(lua, "pesto.math.getRandomState", move |_, ()| {
+            Ok(format!("{:016x}", state.borrow().random.state))
+        })
+        .unwrap();
+
+    let state = math_state.clone();
+    let math_set_random_state = arguments::function(
+        lua,
+        "pesto.math.setRandomState",
+        move |_, random_state: String| {
+            if random_state.len() != 16 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid random state '{}', expected 16 hex digits.",
+                    random_state
+                )));
+            }
+
+            state.borrow_mut().random.state =
+                u64::from_str_radix(&random_state, 16).map_err(|_| {
+                    LuaError::RuntimeError(format!(
+                        "Invalid random state '{}', expected 16 hex digits.",
+                        random_state
+                    ))
+                })?;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    // Coherent noise in [-1, 1] for one to three dimensions
+    let state = math_state.clone();
+    let math_noise = arguments::function(
+        lua,
+        "pesto.math.noise",
+        move |_, (x, y, z): (f64, Option<f64>, Option<f64>)| {
+            Ok(state
+                .borrow()
+                .noise
+                .get(x, y.unwrap_or(0.0), z.unwrap_or(0.0)))
+        },
+    )
+    .unwrap();
+
+    // Vector helpers behave like their pesto.utils (lume) counterparts, so
+    // lerp clamps its amount to [0, 1] and distance can return it squared
+    let math_distance = arguments::function(
+        lua,
+        "pesto.math.distance",
+        |_, (x1, y1, x2, y2, squared): (f64, f64, f64, f64, Option<bool>)| {
+            let distance_squared = (x2 - x1).powi(2) + (y2 - y1).powi(2);
+
+            Ok(if squared.unwrap_or(false) {
+                distance_squared
+            } else {
+                distance_squared.sqrt()
+            })
+        },
+    )
+    .unwrap();
+
+    let math_angle = arguments::function(
+        lua,
+        "pesto.math.angle",
+        |_, (x1, y1, x2, y2): (f64, f64, f64, f64)| Ok((y2 - y1).atan2(x2 - x1)),
+    )
+    .unwrap();
+
+    let math_length = arguments::function(lua, "pesto.math.length", |_, (x, y): (f64, f64)| {
+        Ok(x.hypot(y))
+    })
+    .unwrap();
+
+    // The zero vector stays zero instead of turning into NaN
+    let math_normalize =
+        arguments::function(lua, "pesto.math.normalize", |_, (x, y): (f64, f64)| {
+            let length = x.hypot(y);
+
+            if length == 0.0 {
+                Ok((0.0, 0.0))
+            } else {
+                Ok((x / length, y / length))
+            }
+        })
+        .unwrap();
+
+    let math_lerp = arguments::function(
+        lua,
+        "pesto.math.lerp",
+        |_, (a, b, amount): (f64, f64, f64)| Ok(a + (b - a) * amount.clamp(0.0, 1.0)),
+    )
+    .unwrap();
+
+    let math_clamp = arguments::function(
+        lua,
+        "pesto.math.clamp",
+        |_, (value, min, max): (f64, f64, f64)| Ok(value.max(min).min(max)),
+    )
+    .unwrap();
+
+    math_table.set("random", math_random).unwrap();
+    math_table
+        .set("setRandomSeed", math_set_random_seed)
+        .unwrap();
+    math_table
+        .set("getRandomState", math_get_random_state)
+        .unwrap();
+    math_table
+        .set("setRandomState", math_set_random_state)
+        .unwrap();
+
+    math_table.set("noise", math_noise).unwrap();
+    math_table.set("distance", math_distance).unwrap();
+    math_table.set("angle", math_angle).unwrap();
+    math_table.set("length", math_length).unwrap();
+    math_table.set("normalize", math_normalize).unwrap();
+    math_table.set("lerp", math_lerp).unwrap();
+    math_table.set("clamp", math_clamp).unwrap();
+
+    pesto_table.set("math", math_table).unwrap();
+}
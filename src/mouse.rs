@@ -0,0 +1,161 @@
+use crate::arguments;
+use macroquad::prelude::*;
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// Rust side mouse state, updated every frame by the main loop
+pub struct MouseState {
+    pub position: Vec2,
+    delta: Vec2,
+    window_position: Option<Vec2>,
+    visible: bool,
+    grabbed: bool,
+}
+
+impl Default for MouseState {
+    fn default() -> Self {
+        MouseState {
+            position: Vec2::ZERO,
+            delta: Vec2::ZERO,
+            window_position: None,
+            visible: true,
+            grabbed: false,
+        }
+    }
+}
+
+impl MouseState {
+    // Convert the window position into virtual coordinates given the
+    // letterbox offset and scale. The delta is measured in the window so
+    // resizing doesn't count as movement, and is zero on the first frame
+    pub fn update(&mut self, window_position: Vec2, offset: Vec2, scale: f32) {
+        self.position = (window_position - offset) / scale;
+        self.delta = self
+            .window_position
+            .map_or(Vec2::ZERO, |previous| (window_position - previous) / scale);
+        self.window_position = Some(window_position);
+    }
+
+    // Give the cursor back to the user, called while the error screen is
+    // shown so a crash can't trap it in the window
+    pub fn release(&mut self) {
+        if self.grabbed {
+            set_cursor_grab(false);
+            self.grabbed = false;
+        }
+
+        if !self.visible {
+            show_mouse(true);
+            self.visible = true;
+        }
+    }
+}
+
+// Look up the MouseButton for a button number or name
+fn parse_mouse_button(button: LuaValue) -> LuaResult<MouseButton> {
+    match button {
+        LuaValue::Integer(1) => Ok(MouseButton::Left),
+        LuaValue::Integer(2) => Ok(MouseButton::Right),
+        LuaValue::Integer(3) => Ok(MouseButton::Middle),
+        LuaValue::String(name) => match name.to_str()? {
+            "left" => Ok(MouseButton::Left),
+            "right" => Ok(MouseButton::Right),
+            "middle" => Ok(MouseButton::Middle),
+            name => Err(LuaError::RuntimeError(format!(
+                "Invalid mouse button '{}'.",
+                name
+            ))),
+        },
+        _ => Err(LuaError::RuntimeError(
+            "Invalid mouse button, expected 1, 2, 3 or a button name.".to_string(),
+        )),
+    }
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, mouse_state: &Rc<RefCell<MouseState>>) {
+    let mouse_table = lua.create_table().unwrap();
+
+    // Position in virtual coordinates, already corrected for the letterbox
+    let state = mouse_state.clone();
+    let mouse_get_position = arguments::function(lua, "pesto.mouse.getPosition", move |_, ()| {
+        let position = state.borrow().position;
+
+        Ok((position.x, position.y))
+    })
+    .unwrap();
+
+    // Movement since the last frame in virtual units, keeps working while
+    // the cursor is grabbed
+    let state = mouse_state.clone();
+    let mouse_get_delta = arguments::function(lua, "pesto.mouse.getDelta", move |_, ()| {
+        let delta = state.borrow().delta;
+
+        Ok((delta.x, delta.y))
+    })
+    .unwrap();
+
+    let mouse_is_down = arguments::function(lua, "pesto.mouse.isDown", |_, button: LuaValue| {
+        Ok(is_mouse_button_down(parse_mouse_button(button)?))
+    })
+    .unwrap();
+
+    let mouse_is_pressed =
+        arguments::function(lua, "pesto.mouse.isPressed", |_, button: LuaValue| {
+            Ok(is_mouse_button_pressed(parse_mouse_button(button)?))
+        })
+        .unwrap();
+
+    let mouse_is_released =
+        arguments::function(lua, "pesto.mouse.isReleased", |_, button: LuaValue| {
+            Ok(is_mouse_button_released(parse_mouse_button(button)?))
+        })
+        .unwrap();
+
+    let mouse_get_wheel =
+        arguments::function(lua, "pesto.mouse.getWheel", |_, ()| Ok(mouse_wheel())).unwrap();
+
+    let state = mouse_state.clone();
+    let mouse_set_visible =
+        arguments::function(lua, "pesto.mouse.setVisible", move |_, visible: bool| {
+            show_mouse(visible);
+            state.borrow_mut().visible = visible;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = mouse_state.clone();
+    let mouse_is_visible = arguments::function(lua, "pesto.mouse.isVisible", move |_, ()| {
+        Ok(state.borrow().visible)
+    })
+    .unwrap();
+
+    let state = mouse_state.clone();
+    let mouse_set_grabbed =
+        arguments::function(lua, "pesto.mouse.setGrabbed", move |_, grabbed: bool| {
+            set_cursor_grab(grabbed);
+            state.borrow_mut().grabbed = grabbed;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let state = mouse_state.clone();
+    let mouse_is_grabbed = arguments::function(lua, "pesto.mouse.isGrabbed", move |_, ()| {
+        Ok(state.borrow().grabbed)
+    })
+    .unwrap();
+
+    mouse_table.set("getPosition", mouse_get_position).unwrap();
+    mouse_table.set("getDelta", mouse_get_delta).unwrap();
+    mouse_table.set("isDown", mouse_is_down).unwrap();
+    mouse_table.set("isPressed", mouse_is_pressed).unwrap();
+    mouse_table.set("isReleased", mouse_is_released).unwrap();
+    mouse_table.set("getWheel", mouse_get_wheel).unwrap();
+    mouse_table.set("setVisible", mouse_set_visible).unwrap();
+    mouse_table.set("isVisible", mouse_is_visible).unwrap();
+    mouse_table.set("setGrabbed", mouse_set_grabbed).unwrap();
+    mouse_table.set("isGrabbed", mouse_is_grabbed).unwrap();
+
+    pesto_table.set("mouse", mouse_table).unwrap();
+}
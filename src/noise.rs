@@ -0,0 +1,102 @@
+// Improved Perlin noise (Ken Perlin, 2002). Lower dimensions are slices of
+// the 3D noise, every result is in [-1, 1] and is 0 on whole coordinates
+pub struct Noise {
+    permutation: [u8; 512],
+}
+
+impl Noise {
+    // The permutation table is shuffled with its own generator so noise
+    // doesn't consume numbers from pesto.math.random
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut state = seed;
+
+        for i in (1..table.len()).rev() {
+            // splitmix64
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+
+            table.swap(i, (z % (i as u64 + 1)) as usize);
+        }
+
+        Noise {
+            permutation: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    pub fn get(&self, x: f64, y: f64, z: f64) -> f64 {
+        let p = &self.permutation;
+
+        let xi = (x.floor() as i64 & 255) as usize;
+        let yi = (y.floor() as i64 & 255) as usize;
+        let zi = (z.floor() as i64 & 255) as usize;
+
+        let x = x - x.floor();
+        let y = y - y.floor();
+        let z = z - z.floor();
+
+        let u = fade(x);
+        let v = fade(y);
+        let w = fade(z);
+
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+        .clamp(-1.0, 1.0)
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Dot product with one of 12 gradient directions picked by the hash
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -0,0 +1,140 @@
+use macroquad::prelude::*;
+use std::{collections::VecDeque, time::Duration};
+
+// Frames kept for the graph, one bar each
+const MAX_FRAMES: usize = 120;
+
+// Frame time filling the graph's height, two frames at 60 fps
+const GRAPH_SCALE: f32 = 1.0 / 30.0;
+
+const FONT_SIZE: f32 = 20.0;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.25;
+const WIDTH: f32 = 250.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+
+const UPDATE_COLOR: Color = SKYBLUE;
+const DRAW_COLOR: Color = ORANGE;
+const PRESENT_COLOR: Color = VIOLET;
+
+// Time spent in each part of a frame. Draw calls are only queued while the
+// game draws, the GPU does its work when the frame is presented, so a slow
+// scene shows up as present time
+#[derive(Clone, Copy, Default)]
+pub struct FrameTiming {
+    pub update: Duration,
+    pub draw: Duration,
+    pub present: Duration,
+}
+
+// Recent frame timings, shown over the game while the profiler is open
+#[derive(Default)]
+pub struct ProfilerState {
+    frames: VecDeque<FrameTiming>,
+    visible: bool,
+}
+
+impl ProfilerState {
+    pub fn record(&mut self, timing: FrameTiming) {
+        if self.frames.len() == MAX_FRAMES {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(timing);
+    }
+
+    // Milliseconds spent in each part averaged over the recorded frames
+    fn averages(&self) -> (f32, f32, f32) {
+        let count = self.frames.len().max(1) as f32;
+        let total = |part: fn(&FrameTiming) -> Duration| {
+            self.frames
+                .iter()
+                .map(|frame| part(frame).as_secs_f32())
+                .sum::<f32>()
+                * 1000.0
+                / count
+        };
+
+        (
+            total(|frame| frame.update),
+            total(|frame| frame.draw),
+            total(|frame| frame.present),
+        )
+    }
+}
+
+// Toggle the profiler with F3, then draw it in the top right corner of the
+// virtual resolution
+pub fn update_and_draw(state: &mut ProfilerState, virtual_size: Vec2) {
+    if is_key_pressed(KeyCode::F3) {
+        state.visible = !state.visible;
+    }
+
+    if !state.visible {
+        return;
+    }
+
+    let x = virtual_size.x - WIDTH - 10.0;
+    let y = 10.0;
+
+    draw_rectangle(
+        x,
+        y,
+        WIDTH,
+        LINE_HEIGHT * 4.0 + GRAPH_HEIGHT + 20.0,
+        Color::new(0.0, 0.0, 0.0, 0.8),
+    );
+
+    let (update, draw, present) = state.averages();
+
+    let lines = [
+        (
+            format!("FPS {} ({:.2} ms)", get_fps(), update + draw + present),
+            WHITE,
+        ),
+        (format!("update  {:.2} ms", update), UPDATE_COLOR),
+        (format!("draw    {:.2} ms", draw), DRAW_COLOR),
+        (format!("present {:.2} ms", present), PRESENT_COLOR),
+    ];
+
+    for (i, (line, color)) in lines.iter().enumerate() {
+        draw_text(
+            line,
+            x + 10.0,
+            y + 5.0 + (i + 1) as f32 * LINE_HEIGHT,
+            FONT_SIZE,
+            *color,
+        );
+    }
+
+    // Newest frame on the right, each bar stacks the parts of one frame
+    let graph_bottom = y + LINE_HEIGHT * 4.0 + GRAPH_HEIGHT + 10.0;
+    let bar_width = (WIDTH - 20.0) / MAX_FRAMES as f32;
+    let first = MAX_FRAMES - state.frames.len();
+
+    for (i, frame) in state.frames.iter().enumerate() {
+        let bar_x = x + 10.0 + (first + i) as f32 * bar_width;
+        let mut bar_y = graph_bottom;
+
+        for (part, color) in [
+            (frame.update, UPDATE_COLOR),
+            (frame.draw, DRAW_COLOR),
+            (frame.present, PRESENT_COLOR),
+        ] {
+            let height = (part.as_secs_f32() / GRAPH_SCALE * GRAPH_HEIGHT)
+                .min(bar_y - (graph_bottom - GRAPH_HEIGHT));
+
+            draw_rectangle(bar_x, bar_y - height, bar_width, height, color);
+            bar_y -= height;
+        }
+    }
+
+    // Frames reaching the line took longer than 60 fps allows
+    draw_line(
+        x + 10.0,
+        graph_bottom - GRAPH_HEIGHT / 2.0,
+        x + WIDTH - 10.0,
+        graph_bottom - GRAPH_HEIGHT / 2.0,
+        1.0,
+        Color::new(1.0, 1.0, 1.0, 0.3),
+    );
+}
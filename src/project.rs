@@ -0,0 +1,122 @@
+use crate::archive::Archive;
+use mlua::prelude::*;
+use std::{collections::BTreeSet, fs, io, path::PathBuf, rc::Rc};
+
+// Where the files of the running project come from, the archive when the
+// game is packaged and the project directory otherwise
+pub struct Project {
+    pub directory: PathBuf,
+    pub archive: Option<Archive>,
+    // Whether the archive was appended to the executable, rather than a zip
+    // passed in place of the project directory
+    pub packaged: bool,
+}
+
+impl Project {
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match &self.archive {
+            Some(archive) => archive
+                .read(path)
+                .map(|contents| contents.to_vec())
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "file not found in the archive")
+                }),
+            None => fs::read(self.directory.join(path)),
+        }
+    }
+
+    pub fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+// Items of a directory inside the archive, matching what walking the
+// directory on disk would return
+pub fn archive_items(
+    archive: &Archive,
+    path: &str,
+    recursive: bool,
+    directories: bool,
+) -> Option<Vec<String>> {
+    let prefix = path.trim_start_matches("./").trim_end_matches('/');
+    let prefix = if prefix == "." { "" } else { prefix };
+
+    let mut items = BTreeSet::new();
+    let mut found = prefix.is_empty();
+
+    for file in archive.paths() {
+        let rest = if prefix.is_empty() {
+            file
+        } else {
+            match file
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => continue,
+            }
+        };
+
+        found = true;
+
+        let parts: Vec<&str> = rest.split('/').collect();
+        let depth = if recursive { parts.len() } else { 1 };
+
+        for i in 1..=depth {
+            let is_file = i == parts.len();
+
+            if is_file || directories {
+                items.insert(parts[..i].join("/"));
+            }
+        }
+    }
+
+    found.then(|| items.into_iter().collect())
+}
+
+// Let require find modules inside the archive in each of the given roots,
+// tried right after package.preload like the file loaders
+pub fn add_archive_loader(lua: &Lua, project: &Rc<Project>, roots: &[String]) {
+    if project.archive.is_none() {
+        return;
+    }
+
+    let project = project.clone();
+    let roots = roots.to_vec();
+    let loader = lua
+        .create_function(move |lua, name: String| {
+            let path = name.replace('.', "/");
+
+            let candidates = roots.iter().flat_map(|root| {
+                let path = if root.is_empty() {
+                    path.clone()
+                } else {
+                    format!("{}/{}", root, path)
+                };
+
+                [format!("{}.lua", path), format!("{}/init.lua", path)]
+            });
+
+            for candidate in candidates {
+                if let Ok(source) = project.read_to_string(&candidate) {
+                    let chunk = lua.load(source).set_name(candidate).into_function()?;
+
+                    return Ok(LuaValue::Function(chunk));
+                }
+            }
+
+            Ok(LuaValue::String(lua.create_string(format!(
+                "\n\tno file '{}.lua' in the archive",
+                path
+            ))?))
+        })
+        .unwrap();
+
+    let package_table: LuaTable = lua.globals().get("package").unwrap();
+    let loaders: LuaTable = package_table.get("loaders").unwrap();
+    let table_table: LuaTable = lua.globals().get("table").unwrap();
+    let insert: LuaFunction = table_table.get("insert").unwrap();
+
+    insert.call::<_, ()>((loaders, 2, loader)).unwrap();
+}
@@ -0,0 +1,93 @@
+use crate::arguments;
+use macroquad::miniquad::window;
+use mlua::prelude::*;
+use std::{env, process::Command};
+
+// Command that opens a url in the default browser. On windows cmd's start
+// would treat & in query strings as a command separator, rundll32 doesn't
+fn browser_command(url: &str) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command.arg(url);
+    command
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable) {
+    let system_table = lua.create_table().unwrap();
+
+    // Decided at compile time, other platforms report their target_os
+    let system_get_os = arguments::function(lua, "pesto.system.getOS", |_, ()| {
+        Ok(if cfg!(target_os = "windows") {
+            "Windows"
+        } else if cfg!(target_os = "linux") {
+            "Linux"
+        } else if cfg!(target_os = "macos") {
+            "macOS"
+        } else if cfg!(target_arch = "wasm32") {
+            "Web"
+        } else {
+            env::consts::OS
+        })
+    })
+    .unwrap();
+
+    // Nil for variables that aren't set or aren't valid unicode
+    let system_get_env = arguments::function(lua, "pesto.system.getEnv", |_, name: String| {
+        Ok(env::var(name).ok())
+    })
+    .unwrap();
+
+    // An empty or non text clipboard reads as an empty string
+    let system_get_clipboard_text =
+        arguments::function(lua, "pesto.system.getClipboardText", |_, ()| {
+            Ok(window::clipboard_get().unwrap_or_default())
+        })
+        .unwrap();
+
+    let system_set_clipboard_text =
+        arguments::function(lua, "pesto.system.setClipboardText", |_, text: String| {
+            window::clipboard_set(&text);
+
+            Ok(())
+        })
+        .unwrap();
+
+    // Only web urls are opened, anything else could launch arbitrary
+    // programs through the platform's opener
+    let system_open_url = arguments::function(lua, "pesto.system.openURL", |_, url: String| {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid url '{}', only http:// and https:// urls can be opened.",
+                url
+            )));
+        }
+
+        browser_command(&url).spawn().map_err(|err| {
+            LuaError::RuntimeError(format!("Could not open url '{}': {}", url, err))
+        })?;
+
+        Ok(())
+    })
+    .unwrap();
+
+    system_table.set("getOS", system_get_os).unwrap();
+    system_table.set("getEnv", system_get_env).unwrap();
+    system_table
+        .set("getClipboardText", system_get_clipboard_text)
+        .unwrap();
+    system_table
+        .set("setClipboardText", system_set_clipboard_text)
+        .unwrap();
+
+    system_table.set("openURL", system_open_url).unwrap();
+
+    pesto_table.set("system", system_table).unwrap();
+}
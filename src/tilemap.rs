@@ -0,0 +1,343 @@
+use crate::{arguments, project::Project};
+use mlua::prelude::*;
+use std::{collections::HashMap, rc::Rc};
+
+// Tiled stores the flips and rotation of a tile in the top bits of its id,
+// they aren't supported so they're dropped
+const FLIP_FLAGS: u32 = 0xE000_0000;
+
+// Resolve a path written relative to the map file, as Tiled does for
+// tileset images
+fn resolve_path(map_path: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    let directory = map_path
+        .rsplit_once('/')
+        .map_or("", |(directory, _)| directory);
+
+    for part in directory.split('/').chain(path.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+// Lua exports are chunks returning the map table, JSON exports are decoded
+// with the bundled json library
+fn decode_map<'lua>(lua: &'lua Lua, path: &str, source: &str) -> LuaResult<LuaTable<'lua>> {
+    let map = if path.ends_with(".lua") {
+        lua.load(source).set_name(path).eval()
+    } else if path.ends_with(".json") || path.ends_with(".tmj") {
+        let pesto_table: LuaTable = lua.globals().get("pesto")?;
+        let json_table: LuaTable = pesto_table.get("json")?;
+
+        json_table.get::<_, LuaFunction>("decode")?.call(source)
+    } else {
+        return Err(LuaError::RuntimeError(format!(
+            "Unsupported map format '{}', export the map from Tiled as Lua or JSON.",
+            path
+        )));
+    };
+
+    map.map_err(|err| LuaError::RuntimeError(format!("Could not load map '{}': {}", path, err)))
+}
+
+// Where the tiles of one tileset are cut from in its image
+struct Tileset<'lua> {
+    first_gid: u32,
+    tile_width: f32,
+    tile_height: f32,
+    columns: u32,
+    margin: f32,
+    spacing: f32,
+    image: LuaValue<'lua>,
+    image_width: f32,
+    image_height: f32,
+}
+
+fn load_tileset<'lua>(
+    lua: &'lua Lua,
+    path: &str,
+    tileset: &LuaTable<'lua>,
+) -> LuaResult<Tileset<'lua>> {
+    let name: String = tileset
+        .get::<_, Option<String>>("name")?
+        .unwrap_or_default();
+
+    if tileset.contains_key("source")? || tileset.contains_key("filename")? {
+        return Err(LuaError::RuntimeError(format!(
+            "Tileset '{}' is external, embed it in the map to load it.",
+            name
+        )));
+    }
+
+    let Some(image) = tileset.get::<_, Option<String>>("image")? else {
+        return Err(LuaError::RuntimeError(format!(
+            "Tileset '{}' is a collection of images, only tilesets made from a single image are supported.",
+            name
+        )));
+    };
+
+    let tile_width: f32 = tileset.get("tilewidth")?;
+    let tile_height: f32 = tileset.get("tileheight")?;
+    let columns: u32 = tileset.get("columns")?;
+
+    // Tiles are cut by dividing their ids by the columns
+    if columns == 0 || !(tile_width > 0.0 && tile_height > 0.0) {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid tileset '{}', expected at least one column and a positive tile size.",
+            name
+        )));
+    }
+
+    let pesto_table: LuaTable = lua.globals().get("pesto")?;
+    let graphics_table: LuaTable = pesto_table.get("graphics")?;
+    let image: LuaValue = graphics_table
+        .get::<_, LuaFunction>("newImage")?
+        .call(resolve_path(path, &image))?;
+
+    Ok(Tileset {
+        first_gid: tileset.get("firstgid")?,
+        tile_width,
+        tile_height,
+        columns,
+        margin: tileset.get::<_, Option<f32>>("margin")?.unwrap_or(0.0),
+        spacing: tileset.get::<_, Option<f32>>("spacing")?.unwrap_or(0.0),
+        image,
+        image_width: tileset.get("imagewidth")?,
+        image_height: tileset.get("imageheight")?,
+    })
+}
+
+// Build one sprite batch per tileset used by a tile layer, tiles keep the
+// layer's offset and opacity. Tiles taller than the grid grow upwards from
+// the bottom of their cell, like Tiled draws them
+fn build_layer<'lua>(
+    lua: &'lua Lua,
+    map: &LuaTable<'lua>,
+    layer: &LuaTable<'lua>,
+    tilesets: &[Tileset<'lua>],
+) -> LuaResult<LuaTable<'lua>> {
+    let name: String = layer.get::<_, Option<String>>("name")?.unwrap_or_default();
+
+    if layer.contains_key("chunks")? {
+        return Err(LuaError::RuntimeError(format!(
+            "Layer '{}' is infinite, only fixed size maps are supported.",
+            name
+        )));
+    }
+
+    // Encoded layers have their data as a string instead of a list of ids
+    let data = match layer.get::<_, LuaValue>("data")? {
+        LuaValue::Table(data) => data,
+        LuaValue::String(_) => {
+            return Err(LuaError::RuntimeError(format!(
+                "Layer '{}' is compressed or base64 encoded, export it with CSV or Lua encoding.",
+                name
+            )))
+        }
+        _ => {
+            return Err(LuaError::RuntimeError(format!(
+                "Layer '{}' has no tile data.",
+                name
+            )))
+        }
+    };
+
+    let width: u32 = layer.get("width")?;
+
+    if width == 0 {
+        return Err(LuaError::RuntimeError(format!(
+            "Invalid layer '{}', expected a width of at least one tile.",
+            name
+        )));
+    }
+
+    let tile_width: f32 = map.get("tilewidth")?;
+    let tile_height: f32 = map.get("tileheight")?;
+    let offset_x = layer.get::<_, Option<f32>>("offsetx")?.unwrap_or(0.0);
+    let offset_y = layer.get::<_, Option<f32>>("offsety")?.unwrap_or(0.0);
+    let opacity = layer.get::<_, Option<f32>>("opacity")?.unwrap_or(1.0);
+
+    let pesto_table: LuaTable = lua.globals().get("pesto")?;
+    let graphics_table: LuaTable = pesto_table.get("graphics")?;
+    let new_quad: LuaFunction = graphics_table.get("newQuad")?;
+    let new_sprite_batch: LuaFunction = graphics_table.get("newSpriteBatch")?;
+    let get_color: LuaFunction = graphics_table.get("getColor")?;
+    let set_color: LuaFunction = graphics_table.get("setColor")?;
+
+    let mut batches: Vec<Option<LuaAnyUserData>> = vec![None; tilesets.len()];
+    let mut quads: HashMap<u32, LuaValue> = HashMap::new();
+
+    let color: LuaMultiValue = get_color.call(())?;
+    set_color.call::<_, ()>((1.0, 1.0, 1.0, opacity))?;
+
+    for (index, gid) in data.sequence_values::<u32>().enumerate() {
+        let gid = gid? & !FLIP_FLAGS;
+
+        if gid == 0 {
+            continue;
+        }
+
+        let Some(tileset_index) = tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= gid)
+        else {
+            continue;
+        };
+
+        let tileset = &tilesets[tileset_index];
+
+        let quad = match quads.get(&gid) {
+            Some(quad) => quad.clone(),
+            None => {
+                let id = gid - tileset.first_gid;
+                let column = (id % tileset.columns) as f32;
+                let row = (id / tileset.columns) as f32;
+
+                let quad: LuaValue = new_quad.call((
+                    tileset.margin + column * (tileset.tile_width + tileset.spacing),
+                    tileset.margin + row * (tileset.tile_height + tileset.spacing),
+                    tileset.tile_width,
+                    tileset.tile_height,
+                    tileset.image_width,
+                    tileset.image_height,
+                ))?;
+
+                quads.insert(gid, quad.clone());
+                quad
+            }
+        };
+
+        let batch = match &batches[tileset_index] {
+            Some(batch) => batch.clone(),
+            None => {
+                let batch: LuaAnyUserData = new_sprite_batch.call(tileset.image.clone())?;
+                batches[tileset_index] = Some(batch.clone());
+                batch
+            }
+        };
+
+        let index = index as u32;
+        let x = offset_x + (index % width) as f32 * tile_width;
+        let y = offset_y + (index / width) as f32 * tile_height + tile_height - tileset.tile_height;
+
+        batch.call_method::<_, ()>("add", (x, y, quad))?;
+    }
+
+    set_color.call::<_, ()>(color)?;
+
+    lua.create_sequence_from(batches.into_iter().flatten())
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, project: &Rc<Project>) {
+    let tilemap_table = lua.create_table().unwrap();
+
+    // The map is the table Tiled exported with a sprite batch list added to
+    // every tile layer, the object layers collected in objectgroups and a
+    // draw method. Only orthogonal maps with embedded single image tilesets
+    // are supported, tile flips, image layers, group layers and animated
+    // tiles are ignored
+    let map_project = project.clone();
+    let tilemap_load = arguments::function(lua, "pesto.tilemap.load", move |lua, path: String| {
+        let source = map_project.read_to_string(&path).map_err(|err| {
+            LuaError::RuntimeError(format!("Could not read map '{}': {}", path, err))
+        })?;
+
+        let map = decode_map(lua, &path, &source)?;
+
+        let orientation = map
+            .get::<_, Option<String>>("orientation")?
+            .unwrap_or_else(|| "orthogonal".to_string());
+
+        if orientation != "orthogonal" {
+            return Err(LuaError::RuntimeError(format!(
+                "Unsupported map orientation '{}', only orthogonal maps can be loaded.",
+                orientation
+            )));
+        }
+
+        let mut tilesets = map
+            .get::<_, LuaTable>("tilesets")?
+            .sequence_values::<LuaTable>()
+            .map(|tileset| load_tileset(lua, &path, &tileset?))
+            .collect::<LuaResult<Vec<_>>>()?;
+
+        tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        let object_groups = lua.create_table()?;
+
+        for layer in map
+            .get::<_, LuaTable>("layers")?
+            .sequence_values::<LuaTable>()
+        {
+            let layer = layer?;
+
+            match layer.get::<_, Option<String>>("type")?.as_deref() {
+                Some("tilelayer") => {
+                    layer.set("batches", build_layer(lua, &map, &layer, &tilesets)?)?
+                }
+                Some("objectgroup") => object_groups.push(layer)?,
+                _ => {}
+            }
+        }
+
+        let pesto_table: LuaTable = lua.globals().get("pesto")?;
+        let tilemap_table: LuaTable = pesto_table.get("tilemap")?;
+
+        map.set("objectgroups", object_groups)?;
+        map.set("draw", tilemap_table.get::<_, LuaFunction>("draw")?)?;
+
+        Ok(map)
+    })
+    .unwrap();
+
+    // Draws the visible tile layers in order, optionally moved by x and y
+    let tilemap_draw = arguments::function(
+        lua,
+        "pesto.tilemap.draw",
+        |lua, (map, x, y): (LuaTable, Option<f32>, Option<f32>)| {
+            let pesto_table: LuaTable = lua.globals().get("pesto")?;
+            let graphics_table: LuaTable = pesto_table.get("graphics")?;
+
+            graphics_table
+                .get::<_, LuaFunction>("push")?
+                .call::<_, ()>(())?;
+            graphics_table
+                .get::<_, LuaFunction>("translate")?
+                .call::<_, ()>((x.unwrap_or(0.0), y.unwrap_or(0.0)))?;
+
+            for layer in map
+                .get::<_, LuaTable>("layers")?
+                .sequence_values::<LuaTable>()
+            {
+                let layer = layer?;
+
+                if layer.get::<_, Option<bool>>("visible")? == Some(false) {
+                    continue;
+                }
+
+                if let Some(batches) = layer.get::<_, Option<LuaTable>>("batches")? {
+                    for batch in batches.sequence_values::<LuaAnyUserData>() {
+                        batch?.call_method::<_, ()>("draw", ())?;
+                    }
+                }
+            }
+
+            graphics_table
+                .get::<_, LuaFunction>("pop")?
+                .call::<_, ()>(())
+        },
+    )
+    .unwrap();
+
+    tilemap_table.set("load", tilemap_load).unwrap();
+    tilemap_table.set("draw", tilemap_draw).unwrap();
+
+    pesto_table.set("tilemap", tilemap_table).unwrap();
+}
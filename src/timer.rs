@@ -0,0 +1,150 @@
+use crate::arguments;
+use macroquad::prelude::*;
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// Most updates run in a single frame before leftover time is dropped, so a
+// long stall can't snowball into ever more updates
+const MAX_FIXED_STEPS: u32 = 5;
+
+// Rust side timer state, decides how update is called every frame
+pub struct TimerState {
+    pub fixed_step: Option<f32>,
+    max_delta: Option<f32>,
+    delta: f32,
+    accumulator: f32,
+}
+
+impl TimerState {
+    pub fn new(fixed_step: Option<f32>) -> Self {
+        TimerState {
+            fixed_step,
+            max_delta: None,
+            delta: 0.0,
+            accumulator: 0.0,
+        }
+    }
+
+    // Number of updates to run for this frame and the delta passed to each
+    pub fn steps(&mut self, frame_time: f32) -> (u32, f32) {
+        // Hitches are clamped before they reach either kind of update
+        let frame_time = self.max_delta.map_or(frame_time, |max| frame_time.min(max));
+        self.delta = frame_time;
+
+        let Some(step) = self.fixed_step else {
+            return (1, frame_time);
+        };
+
+        self.accumulator += frame_time;
+
+        let steps = ((self.accumulator / step) as u32).min(MAX_FIXED_STEPS);
+
+        if steps == MAX_FIXED_STEPS {
+            self.accumulator = 0.0;
+        } else {
+            self.accumulator -= steps as f32 * step;
+        }
+
+        (steps, step)
+    }
+
+    // How far the leftover time is between the last fixed update and the
+    // next, for drawing interpolated between the two. Without a fixed step
+    // every draw follows an update that just happened
+    pub fn alpha(&self) -> f32 {
+        match self.fixed_step {
+            Some(step) => (self.accumulator / step).clamp(0.0, 1.0),
+            None => 1.0,
+        }
+    }
+}
+
+fn check_step(step: Option<f32>) -> LuaResult<Option<f32>> {
+    match step {
+        Some(step) if !(step.is_finite() && step > 0.0) => Err(LuaError::RuntimeError(format!(
+            "Invalid fixed step {}, expected a positive number.",
+            step
+        ))),
+        _ => Ok(step),
+    }
+}
+
+// Extends the tick library mounted as pesto.timer, so it has to be
+// registered after the external libraries are loaded
+pub fn register(lua: &Lua, pesto_table: &LuaTable, timer_state: &Rc<RefCell<TimerState>>) {
+    let timer_table: LuaTable = pesto_table.get("timer").unwrap();
+
+    let timer_get_time =
+        arguments::function(lua, "pesto.timer.getTime", |_, ()| Ok(get_time())).unwrap();
+
+    let timer_get_fps =
+        arguments::function(lua, "pesto.timer.getFPS", |_, ()| Ok(get_fps())).unwrap();
+
+    // Passing nothing goes back to one variable step update per frame
+    let state = timer_state.clone();
+    let timer_set_fixed_step = arguments::function(
+        lua,
+        "pesto.timer.setFixedStep",
+        move |_, step: Option<f32>| {
+            let mut state = state.borrow_mut();
+
+            state.fixed_step = check_step(step)?;
+            state.accumulator = 0.0;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = timer_state.clone();
+    let timer_get_fixed_step =
+        arguments::function(lua, "pesto.timer.getFixedStep", move |_, ()| {
+            Ok(state.borrow().fixed_step)
+        })
+        .unwrap();
+
+    // Time of the last frame after clamping, whatever the update delta was
+    let state = timer_state.clone();
+    let timer_get_delta = arguments::function(lua, "pesto.timer.getDelta", move |_, ()| {
+        Ok(state.borrow().delta)
+    })
+    .unwrap();
+
+    // Passing nothing removes the limit
+    let state = timer_state.clone();
+    let timer_set_max_delta = arguments::function(
+        lua,
+        "pesto.timer.setMaxDelta",
+        move |_, max: Option<f32>| {
+            if let Some(max) = max.filter(|max| max.is_nan() || *max <= 0.0) {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid max delta {}, expected a positive number.",
+                    max
+                )));
+            }
+
+            state.borrow_mut().max_delta = max;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = timer_state.clone();
+    let timer_get_max_delta = arguments::function(lua, "pesto.timer.getMaxDelta", move |_, ()| {
+        Ok(state.borrow().max_delta)
+    })
+    .unwrap();
+
+    timer_table.set("getTime", timer_get_time).unwrap();
+    timer_table.set("getFPS", timer_get_fps).unwrap();
+    timer_table.set("getDelta", timer_get_delta).unwrap();
+    timer_table
+        .set("setFixedStep", timer_set_fixed_step)
+        .unwrap();
+    timer_table
+        .set("getFixedStep", timer_get_fixed_step)
+        .unwrap();
+    timer_table.set("setMaxDelta", timer_set_max_delta).unwrap();
+    timer_table.set("getMaxDelta", timer_get_max_delta).unwrap();
+}
@@ -0,0 +1,91 @@
+use crate::arguments;
+use macroquad::prelude::*;
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// A touch point in virtual coordinates
+struct TouchPoint {
+    id: u64,
+    phase: TouchPhase,
+    position: Vec2,
+}
+
+// Rust side touch state, updated every frame by the main loop
+#[derive(Default)]
+pub struct TouchState {
+    touches: Vec<TouchPoint>,
+}
+
+impl TouchState {
+    // Convert this frame's touches into virtual coordinates with the same
+    // letterbox offset and scale as the mouse, ordered by id so the list
+    // doesn't shuffle between frames
+    pub fn update(&mut self, offset: Vec2, scale: f32) {
+        self.touches = touches()
+            .into_iter()
+            .map(|touch| TouchPoint {
+                id: touch.id,
+                phase: touch.phase,
+                position: (touch.position - offset) / scale,
+            })
+            .collect();
+
+        self.touches.sort_by_key(|touch| touch.id);
+    }
+}
+
+// Callback name, id and position of every touch that started, moved or
+// ended this frame, for pesto.touchpressed, touchmoved and touchreleased
+pub fn touch_events(state: &TouchState) -> Vec<(&'static str, i64, f32, f32)> {
+    state
+        .touches
+        .iter()
+        .filter_map(|touch| {
+            let callback = match touch.phase {
+                TouchPhase::Started => "touchpressed",
+                TouchPhase::Moved => "touchmoved",
+                TouchPhase::Ended | TouchPhase::Cancelled => "touchreleased",
+                TouchPhase::Stationary => return None,
+            };
+
+            Some((
+                callback,
+                touch.id as i64,
+                touch.position.x,
+                touch.position.y,
+            ))
+        })
+        .collect()
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, touch_state: &Rc<RefCell<TouchState>>) {
+    let touch_table = lua.create_table().unwrap();
+
+    // Touches still on the screen, each a table with an id and its position
+    // in virtual coordinates
+    let state = touch_state.clone();
+    let touch_get_touches = arguments::function(lua, "pesto.touch.getTouches", move |lua, ()| {
+        let touches = lua.create_table()?;
+
+        for touch in state
+            .borrow()
+            .touches
+            .iter()
+            .filter(|touch| !matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled))
+        {
+            let table = lua.create_table()?;
+            table.set("id", touch.id as i64)?;
+            table.set("x", touch.position.x)?;
+            table.set("y", touch.position.y)?;
+
+            touches.push(table)?;
+        }
+
+        Ok(touches)
+    })
+    .unwrap();
+
+    touch_table.set("getTouches", touch_get_touches).unwrap();
+
+    pesto_table.set("touch", touch_table).unwrap();
+}
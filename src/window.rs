@@ -0,0 +1,118 @@
+use crate::arguments;
+use macroquad::prelude::*;
+use mlua::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// Rust side window state
+pub struct WindowState {
+    pub title: String,
+    pub fullscreen: bool,
+}
+
+pub fn register(lua: &Lua, pesto_table: &LuaTable, window_state: &Rc<RefCell<WindowState>>) {
+    let window_table = lua.create_table().unwrap();
+
+    // miniquad can't rename a window once it is open, so the window keeps
+    // the title from conf.lua and this returns false, like setPosition
+    let window_set_title =
+        arguments::function(lua, "pesto.window.setTitle", |_, _title: String| Ok(false)).unwrap();
+
+    let state = window_state.clone();
+    let window_get_title = arguments::function(lua, "pesto.window.getTitle", move |_, ()| {
+        Ok(state.borrow().title.clone())
+    })
+    .unwrap();
+
+    let window_get_width = arguments::function(lua, "pesto.window.getWidth", |_, ()| {
+        Ok(screen_width() as i32)
+    })
+    .unwrap();
+
+    let window_get_height = arguments::function(lua, "pesto.window.getHeight", |_, ()| {
+        Ok(screen_height() as i32)
+    })
+    .unwrap();
+
+    // miniquad has no way to query monitors, so the desktop size is only
+    // known while fullscreen. Otherwise this returns nil and a message
+    // instead of a size that would be wrong
+    let state = window_state.clone();
+    let window_get_desktop_dimensions =
+        arguments::function(lua, "pesto.window.getDesktopDimensions", move |lua, ()| {
+            if state.borrow().fullscreen {
+                (screen_width() as i32, screen_height() as i32).into_lua_multi(lua)
+            } else {
+                (
+                    LuaValue::Nil,
+                    "The desktop size is only known while fullscreen.",
+                )
+                    .into_lua_multi(lua)
+            }
+        })
+        .unwrap();
+
+    // Monitors can't be enumerated, so this always returns nil and a
+    // message
+    let window_get_display_count =
+        arguments::function(lua, "pesto.window.getDisplayCount", |_, ()| {
+            Ok((LuaValue::Nil, "The number of displays can't be queried."))
+        })
+        .unwrap();
+
+    // miniquad can't move windows on any platform yet, so both of these
+    // leave the window where the system placed it and return false
+    let window_set_position = arguments::function(
+        lua,
+        "pesto.window.setPosition",
+        |_, (_x, _y): (i32, i32)| Ok(false),
+    )
+    .unwrap();
+
+    let window_center = arguments::function(lua, "pesto.window.center", |_, ()| Ok(false)).unwrap();
+
+    // The letterbox scale is recomputed every frame, so it adapts to the
+    // new window size on its own
+    let state = window_state.clone();
+    let window_set_fullscreen = arguments::function(
+        lua,
+        "pesto.window.setFullscreen",
+        move |_, fullscreen: bool| {
+            set_fullscreen(fullscreen);
+
+            state.borrow_mut().fullscreen = fullscreen;
+
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let state = window_state.clone();
+    let window_is_fullscreen =
+        arguments::function(lua, "pesto.window.isFullscreen", move |_, ()| {
+            Ok(state.borrow().fullscreen)
+        })
+        .unwrap();
+
+    window_table.set("setTitle", window_set_title).unwrap();
+    window_table.set("getTitle", window_get_title).unwrap();
+    window_table.set("getWidth", window_get_width).unwrap();
+    window_table.set("getHeight", window_get_height).unwrap();
+    window_table
+        .set("getDesktopDimensions", window_get_desktop_dimensions)
+        .unwrap();
+    window_table
+        .set("getDisplayCount", window_get_display_count)
+        .unwrap();
+    window_table
+        .set("setPosition", window_set_position)
+        .unwrap();
+    window_table.set("center", window_center).unwrap();
+    window_table
+        .set("setFullscreen", window_set_fullscreen)
+        .unwrap();
+    window_table
+        .set("isFullscreen", window_is_fullscreen)
+        .unwrap();
+
+    pesto_table.set("window", window_table).unwrap();
+}